@@ -0,0 +1,126 @@
+use anyhow::Result;
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    io::Reader,
+    AnimationDecoder, Frames, ImageFormat, RgbaImage,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Like browsers do, frames which are shown for less than MIN_FRAME_DELAY are
+// slowed down to DEFAULT_FRAME_DELAY. Many files rely on it.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+// Frames are kept at full size, for zooming in. Long animations of large
+// images are cut short after this many bytes of them.
+const MAX_FRAMES_BYTES: usize = 256 * 1024 * 1024;
+
+/// Every frame of an animated GIF, PNG or WebP, fully composed, and how long
+/// each is shown.
+pub struct Animation {
+    frames: Vec<RgbaImage>,
+    delays: Vec<Duration>,
+}
+
+impl Animation {
+    /// Decode an animation, which takes a while, it's done by the
+    /// `Prefetcher`. Still images, including GIFs of a single frame, give
+    /// `None`.
+    pub fn decode(image_path: &Path) -> Result<Option<Animation>> {
+        let format = Reader::open(image_path)?.with_guessed_format()?.format();
+        let reader = BufReader::new(File::open(image_path)?);
+        let frames = match format {
+            Some(ImageFormat::Gif) => GifDecoder::new(reader)?.into_frames(),
+            Some(ImageFormat::Png) => {
+                let decoder = PngDecoder::new(reader)?;
+                if !decoder.is_apng() {
+                    return Ok(None);
+                }
+                decoder.apng().into_frames()
+            }
+            Some(ImageFormat::WebP) => {
+                let decoder = WebPDecoder::new(reader)?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                decoder.into_frames()
+            }
+            _ => return Ok(None),
+        };
+
+        Animation::collect(frames)
+    }
+
+    fn collect(frames: Frames) -> Result<Option<Animation>> {
+        let mut animation = Animation {
+            frames: vec![],
+            delays: vec![],
+        };
+        let mut bytes = 0;
+        for frame in frames {
+            let frame = frame?;
+            bytes += frame.buffer().len();
+            if bytes > MAX_FRAMES_BYTES && !animation.frames.is_empty() {
+                break;
+            }
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_millis((numerator / denominator.max(1)) as u64);
+            animation.delays.push(if delay < MIN_FRAME_DELAY {
+                DEFAULT_FRAME_DELAY
+            } else {
+                delay
+            });
+            animation.frames.push(frame.into_buffer());
+        }
+
+        if animation.frames.len() < 2 {
+            return Ok(None);
+        }
+        Ok(Some(animation))
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frame(&self, index: usize) -> &RgbaImage {
+        &self.frames[index % self.frames.len()]
+    }
+
+    pub fn delay(&self, index: usize) -> Duration {
+        self.delays[index % self.delays.len()]
+    }
+}
+
+/// The animation being played, for drawing its frames. Only one is kept, the
+/// frames of a long one take a lot of memory.
+static PLAYING: Mutex<Option<(PathBuf, Arc<Animation>)>> = Mutex::new(None);
+
+/// Make `animation`, decoded from `image_path`, the one being played.
+pub fn play(image_path: &Path, animation: Arc<Animation>) {
+    if let Ok(mut playing) = PLAYING.lock() {
+        *playing = Some((image_path.to_path_buf(), animation));
+    }
+}
+
+/// Let go of the animation being played.
+pub fn stop() {
+    if let Ok(mut playing) = PLAYING.lock() {
+        *playing = None;
+    }
+}
+
+/// The animation in `image_path`, if it's the one being played.
+pub fn playing_animation(image_path: &Path) -> Option<Arc<Animation>> {
+    let playing = PLAYING.lock().ok()?;
+    match playing.as_ref() {
+        Some((path, animation)) if path == image_path => Some(animation.clone()),
+        _ => None,
+    }
+}
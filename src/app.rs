@@ -1,21 +1,47 @@
 use anyhow::{anyhow, Result};
 use std::io::prelude::*;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::File,
     path::{Path, PathBuf},
-    time::Instant,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
+use crate::animation::{self, Animation};
+use crate::format::ImageFilter;
+use crate::histogram::Histogram;
 use crate::Opt;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum TabId {
     Main,
     Script,
+    Grid,
 }
 
-const TABS: [TabId; 2] = [TabId::Main, TabId::Script];
+const TABS: [TabId; 3] = [TabId::Main, TabId::Script, TabId::Grid];
+
+// How long a message stays in the status
+const STATUS_DURATION: Duration = Duration::from_secs(2);
+// How long to wait for an answer when asked to quit, before saving anyway
+const EXIT_PROMPT_DURATION: Duration = Duration::from_secs(10);
+
+const MIN_ZOOM_PERCENT: u32 = 25;
+const MAX_ZOOM_PERCENT: u32 = 1600;
+// How far a key press pans, in per mille of the image size
+const PAN_STEP: i32 = 50;
+
+/// How much of the current image is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Zoom {
+    /// The whole image, scaled down to fit
+    Fit,
+    /// `percent` of the image size, at 100% one image pixel takes one screen
+    /// pixel. `center` is the point of the image shown in the middle, in per
+    /// mille of its width and height.
+    Scale { percent: u32, center: (u32, u32) },
+}
 
 #[derive(PartialEq, Eq, Clone)]
 pub enum Action {
@@ -39,18 +65,91 @@ impl Action {
     }
 }
 
+/// An image shown in the Main tab, there's more than one when comparing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    /// A reference image, pinned while going through the queue
+    Pinned,
+    Current,
+    Next,
+}
+
+/// Which frame of the current image is shown, when it's animated.
+pub struct Playback {
+    image_path: PathBuf,
+    // `None` for still images, and until the frames are decoded
+    animation: Option<Arc<Animation>>,
+    frame: usize,
+    paused: bool,
+    shown_at: Instant,
+}
+
+/// The histogram of the current image, while the histogram is shown.
+pub struct ImageHistogram {
+    image_path: PathBuf,
+    // `None` until it's computed
+    histogram: Option<Result<Arc<Histogram>>>,
+}
+
+/// The parts of the app shown on screen, a frame is only drawn when they
+/// changed since the previous one.
+#[derive(PartialEq, Eq)]
+pub struct View {
+    tab: usize,
+    script_offset: (u16, u16),
+    current: usize,
+    zoom: Zoom,
+    pinned: Option<PathBuf>,
+    compare_next: bool,
+    focus: Pane,
+    actions: usize,
+    last_action: Option<Action>,
+    enable_input: bool,
+    input: Vec<char>,
+    input_idx: usize,
+    grid_cursor: usize,
+    grid_selection: BTreeSet<PathBuf>,
+    grid_anchor: Option<usize>,
+    frame: usize,
+    frame_count: usize,
+    paused: bool,
+    exit_prompt: Option<u64>,
+    show_histogram: bool,
+    histogram_ready: bool,
+    show_all_controls: bool,
+    saved_recently: bool,
+    recent_error: Option<String>,
+}
+
 pub struct App {
     pub tab: usize,
     pub script_offset: (u16, u16),
     pub images: Vec<PathBuf>,
     pub current: usize,
+    pub zoom: Zoom,
+    pub pinned: Option<PathBuf>,
+    pub compare_next: bool,
+    pub focus: Pane,
     pub key_mapping: BTreeMap<char, PathBuf>,
     pub actions: Vec<Action>,
+    // the actions in the script written last
+    pub saved_actions: Vec<Action>,
     pub output: String,
     pub enable_input: bool,
     pub input: Vec<char>,
     pub input_idx: usize,
+    pub grid_cursor: usize,
+    pub grid_selection: BTreeSet<PathBuf>,
+    // start of the range being selected in the grid
+    pub grid_anchor: Option<usize>,
+    pub playback: Option<Playback>,
+    pub show_histogram: bool,
+    pub histogram: Option<ImageHistogram>,
+    pub show_all_controls: bool,
+    // when the app was asked to quit, by a signal, with unsaved changes
+    pub exit_prompt: Option<Instant>,
     pub last_save: Option<Instant>,
+    pub last_error: Option<(String, Instant)>,
 }
 
 impl Default for App {
@@ -59,26 +158,42 @@ impl Default for App {
             tab: 0,
             script_offset: (0, 0),
             current: 0,
+            zoom: Zoom::Fit,
+            pinned: None,
+            compare_next: false,
+            focus: Pane::Current,
             images: vec![],
             key_mapping: BTreeMap::new(),
             actions: vec![],
+            saved_actions: vec![],
             output: "".to_string(),
             enable_input: false,
             input: vec![],
             input_idx: 0,
+            grid_cursor: 0,
+            grid_selection: BTreeSet::new(),
+            grid_anchor: None,
+            playback: None,
+            show_histogram: false,
+            histogram: None,
+            show_all_controls: false,
+            exit_prompt: None,
             last_save: None,
+            last_error: None,
         }
     }
 }
 
 impl App {
     pub fn new(opt: Opt) -> Result<Self> {
-        let images = App::parse_images(opt.input, opt.recurse);
+        let filter = ImageFilter::new(opt.types, opt.ext, opt.sniff)?;
+        let images = App::parse_images(opt.input, opt.recurse, &filter);
         let (key_mapping, actions) = App::parse_key_mapping(opt.bind)?;
 
         Ok(App {
             images,
             key_mapping,
+            saved_actions: actions.clone(),
             actions,
             output: opt.output,
             ..App::default()
@@ -93,6 +208,163 @@ impl App {
         Some(self.images[self.current].clone())
     }
 
+    /// The images most likely to be shown after the current one: the next
+    /// `count` in the queue, then the previous one, shown again when undoing.
+    pub fn neighbours(&self, count: usize) -> Vec<PathBuf> {
+        let next = (self.current + 1).min(self.images.len());
+        let mut images = self.images[next..(next + count).min(self.images.len())].to_vec();
+        if let Some(previous) = self.current.checked_sub(1) {
+            images.push(self.images[previous].clone());
+        }
+        images
+    }
+
+    /// The images to show side by side: the pinned one, the current one and
+    /// the next one, when comparing with it.
+    pub fn panes(&self) -> Vec<(Pane, PathBuf)> {
+        let mut panes = vec![];
+        if let Some(pinned) = &self.pinned {
+            panes.push((Pane::Pinned, pinned.clone()));
+        }
+        if let Some(current) = self.current_image() {
+            panes.push((Pane::Current, current));
+        }
+        if self.compare_next {
+            if let Some(next) = self.images.get(self.current + 1) {
+                panes.push((Pane::Next, next.clone()));
+            }
+        }
+        panes
+    }
+
+    pub fn toggle_pin(&mut self) {
+        self.pinned = match self.pinned {
+            Some(_) => None,
+            None => self.current_image(),
+        };
+        self.focus = Pane::Current;
+    }
+
+    pub fn toggle_compare_next(&mut self) {
+        self.compare_next = !self.compare_next;
+        self.focus = Pane::Current;
+    }
+
+    pub fn cycle_focus(&mut self) {
+        let panes = self.panes();
+        self.focus = match panes.iter().position(|(pane, _)| *pane == self.focus) {
+            Some(i) => panes[(i + 1) % panes.len()].0,
+            None => Pane::Current,
+        };
+    }
+
+    /// Move the image of the focused pane to the front of the queue, so it's
+    /// the one the next action applies to. Returns false, and tells so in the
+    /// status, if it was already sorted.
+    pub fn select_focused(&mut self) -> bool {
+        let focus = self.focus;
+        self.focus = Pane::Current;
+
+        let focused = self
+            .panes()
+            .into_iter()
+            .find(|(pane, _)| *pane == focus)
+            .map(|(_, image)| image);
+        match focused {
+            Some(focused) if self.bring_to_front(&focused) => true,
+            Some(focused) => {
+                let name = focused.file_name().unwrap_or(focused.as_os_str());
+                self.report_error(anyhow!("{} is already sorted", name.to_string_lossy()));
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Move `image` to the front of the queue, making it the current image.
+    /// Returns false if it's not in the queue, i.e. it was already sorted.
+    fn bring_to_front(&mut self, image: &Path) -> bool {
+        match self.images[self.current..].iter().position(|i| i == image) {
+            Some(index) => {
+                let image = self.images.remove(self.current + index);
+                self.images.insert(self.current, image);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn grid_move(&mut self, delta: isize) {
+        let last = self.images.len().saturating_sub(1).max(self.current);
+        self.grid_cursor = (self.grid_cursor as isize + delta)
+            .clamp(self.current as isize, last as isize) as usize;
+    }
+
+    pub fn grid_toggle_selection(&mut self) {
+        if let Some(image) = self.images.get(self.grid_cursor).cloned() {
+            if !self.grid_selection.remove(&image) {
+                self.grid_selection.insert(image);
+            }
+        }
+    }
+
+    /// Start selecting a range of images from the cursor, or add the range
+    /// being selected to the selection.
+    pub fn grid_toggle_range(&mut self) {
+        // the cursor is past the last image once they're all sorted
+        if self.current >= self.images.len() {
+            self.grid_anchor = None;
+            return;
+        }
+        let last = self.images.len() - 1;
+
+        match self.grid_anchor.take() {
+            None => self.grid_anchor = Some(self.grid_cursor.min(last)),
+            Some(anchor) => {
+                let range = anchor.min(self.grid_cursor)..=anchor.max(self.grid_cursor).min(last);
+                for image in self.images[range].iter() {
+                    self.grid_selection.insert(image.clone());
+                }
+            }
+        }
+    }
+
+    pub fn grid_clear_selection(&mut self) {
+        self.grid_selection.clear();
+        self.grid_anchor = None;
+    }
+
+    pub fn is_grid_selected(&self, index: usize) -> bool {
+        let in_range = self.grid_anchor.is_some_and(|anchor| {
+            (anchor.min(self.grid_cursor)..=anchor.max(self.grid_cursor)).contains(&index)
+        });
+        in_range || self.grid_selection.contains(&self.images[index])
+    }
+
+    /// Push an action for every image selected in the grid, in queue order,
+    /// or for the one under the cursor when nothing is selected.
+    pub fn sort_grid_selection<F>(&mut self, action: F)
+    where
+        F: Fn(PathBuf) -> Action,
+    {
+        let mut selected: Vec<PathBuf> = (self.current..self.images.len())
+            .filter(|&index| self.is_grid_selected(index))
+            .map(|index| self.images[index].clone())
+            .collect();
+        if selected.is_empty() {
+            selected.extend(self.images.get(self.grid_cursor).cloned());
+        }
+
+        for image in selected {
+            if self.bring_to_front(&image) {
+                self.push_action(action(image));
+            }
+        }
+
+        self.grid_clear_selection();
+        self.grid_cursor = self.current;
+    }
+
     pub fn pop_action(&mut self) {
         let last_action = self.actions.last().cloned();
 
@@ -111,6 +383,11 @@ impl App {
 
         self.current += action.queue_step();
         self.actions.push(action);
+        // the grid cursor and range only ever point at images left to sort
+        self.grid_cursor = self.grid_cursor.max(self.current);
+        if let Some(anchor) = &mut self.grid_anchor {
+            *anchor = (*anchor).max(self.current);
+        }
     }
 
     pub fn current_tab(&self) -> TabId {
@@ -148,13 +425,68 @@ impl App {
         self.script_offset = (y, x + 1);
     }
 
+    pub fn zoom_in(&mut self) {
+        self.zoom = match self.zoom {
+            Zoom::Fit => Zoom::Scale {
+                percent: 100,
+                center: (500, 500),
+            },
+            Zoom::Scale { percent, center } => Zoom::Scale {
+                percent: (percent * 2).min(MAX_ZOOM_PERCENT),
+                center,
+            },
+        };
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = match self.zoom {
+            Zoom::Scale { percent, center } if percent > MIN_ZOOM_PERCENT => Zoom::Scale {
+                percent: percent / 2,
+                center,
+            },
+            _ => Zoom::Fit,
+        };
+    }
+
+    pub fn zoom_fit(&mut self) {
+        self.zoom = Zoom::Fit;
+    }
+
+    pub fn zoom_actual_size(&mut self) {
+        let center = match self.zoom {
+            Zoom::Fit => (500, 500),
+            Zoom::Scale { center, .. } => center,
+        };
+        self.zoom = Zoom::Scale {
+            percent: 100,
+            center,
+        };
+    }
+
+    /// Move the zoomed in view by `dx` and `dy` steps.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        if let Zoom::Scale { center, .. } = &mut self.zoom {
+            let (x, y) = *center;
+            *center = (
+                (x as i32 + dx * PAN_STEP).clamp(0, 1000) as u32,
+                (y as i32 + dy * PAN_STEP).clamp(0, 1000) as u32,
+            );
+        }
+    }
+
     pub fn rename_current_image(&mut self) {
+        if !self.select_focused() {
+            return;
+        }
         if let Some(current_image) = self.current_image() {
             if let Some(name) = current_image.file_name() {
                 let name: Vec<char> = name.to_str().unwrap().chars().collect();
                 self.input_idx = name.len();
                 self.input = name;
                 self.enable_input = true;
+                // the input is only drawn in the Main tab
+                self.tab = TABS.iter().position(|&tab| tab == TabId::Main).unwrap_or(0);
+                self.script_offset = (0, 0);
             }
         }
     }
@@ -179,10 +511,227 @@ impl App {
         let mut file = File::create(&self.output)?;
         file.write_all(script.as_bytes())?;
 
+        self.saved_actions = self.actions.clone();
         self.last_save = Some(Instant::now());
         Ok(())
     }
 
+    /// Whether anything was sorted since the script was last written.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.actions != self.saved_actions
+    }
+
+    /// Ask whether to save the script before quitting.
+    pub fn prompt_exit(&mut self) {
+        self.exit_prompt = Some(Instant::now());
+    }
+
+    /// How long until the script is saved without an answer to the exit
+    /// prompt, if it's shown.
+    pub fn exit_prompt_left(&self) -> Option<Duration> {
+        self.exit_prompt
+            .map(|prompted| EXIT_PROMPT_DURATION.saturating_sub(prompted.elapsed()))
+    }
+
+    /// Follow the current image, when it changed. Returns it, for its frames
+    /// to be decoded if it's animated, see `start_playback`.
+    pub fn sync_playback(&mut self) -> Option<PathBuf> {
+        let image_path = self.current_image();
+        if self.playback.as_ref().map(|playback| &playback.image_path) == image_path.as_ref() {
+            return None;
+        }
+
+        animation::stop();
+        self.playback = image_path.clone().map(|image_path| Playback {
+            image_path,
+            animation: None,
+            frame: 0,
+            paused: false,
+            shown_at: Instant::now(),
+        });
+        image_path
+    }
+
+    /// Play the frames decoded from `image_path`, unless it's not the current
+    /// image anymore.
+    pub fn start_playback(&mut self, image_path: &Path, animation: Result<Option<Arc<Animation>>>) {
+        let Some(playback) = self
+            .playback
+            .as_mut()
+            .filter(|playback| playback.image_path == image_path)
+        else {
+            return;
+        };
+
+        match animation {
+            Ok(Some(animation)) => {
+                animation::play(image_path, animation.clone());
+                playback.animation = Some(animation);
+                playback.shown_at = Instant::now();
+            }
+            Ok(None) => {}
+            Err(err) => self.report_error(err),
+        }
+    }
+
+    /// Show the next frame of the current image, once the delay of the one
+    /// shown is over.
+    pub fn tick(&mut self) {
+        if self.current_tab() != TabId::Main {
+            return;
+        }
+        if let Some(playback) = self.playback.as_mut() {
+            let Some(animation) = &playback.animation else {
+                return;
+            };
+            if !playback.paused && playback.shown_at.elapsed() >= animation.delay(playback.frame) {
+                playback.frame = (playback.frame + 1) % animation.frame_count();
+                playback.shown_at = Instant::now();
+            }
+        }
+    }
+
+    /// How long until `tick` shows the next frame, if an animation is playing.
+    pub fn next_frame_in(&self) -> Option<Duration> {
+        if self.current_tab() != TabId::Main {
+            return None;
+        }
+        let playback = self.playback.as_ref().filter(|playback| !playback.paused)?;
+        let delay = playback.animation.as_ref()?.delay(playback.frame);
+        Some(delay.saturating_sub(playback.shown_at.elapsed()))
+    }
+
+    pub fn toggle_histogram(&mut self) {
+        self.show_histogram = !self.show_histogram;
+    }
+
+    /// Follow the current image while the histogram is shown. Returns it, for
+    /// its histogram to be computed, see `set_histogram`.
+    pub fn sync_histogram(&mut self) -> Option<PathBuf> {
+        if !self.show_histogram {
+            return None;
+        }
+        let image_path = self.current_image();
+        if self
+            .histogram
+            .as_ref()
+            .map(|histogram| &histogram.image_path)
+            == image_path.as_ref()
+        {
+            return None;
+        }
+
+        self.histogram = image_path.clone().map(|image_path| ImageHistogram {
+            image_path,
+            histogram: None,
+        });
+        image_path
+    }
+
+    /// Show the histogram computed for `image_path`, unless it's not the
+    /// current image anymore.
+    pub fn set_histogram(&mut self, image_path: &Path, histogram: Result<Arc<Histogram>>) {
+        if let Some(current) = self
+            .histogram
+            .as_mut()
+            .filter(|current| current.image_path == image_path)
+        {
+            current.histogram = Some(histogram);
+        }
+    }
+
+    /// The histogram of the current image, once it's computed.
+    pub fn current_histogram(&self) -> Option<&Result<Arc<Histogram>>> {
+        self.histogram.as_ref()?.histogram.as_ref()
+    }
+
+    pub fn toggle_controls(&mut self) {
+        self.show_all_controls = !self.show_all_controls;
+    }
+
+    pub fn toggle_playback(&mut self) {
+        if let Some(playback) = self.playback.as_mut() {
+            playback.paused = !playback.paused;
+            playback.shown_at = Instant::now();
+        }
+    }
+
+    /// Pause, and show the frame `delta` frames away from the one shown.
+    pub fn step_frame(&mut self, delta: isize) {
+        if let Some(playback) = self.playback.as_mut() {
+            if let Some(animation) = &playback.animation {
+                let count = animation.frame_count() as isize;
+                playback.frame = (playback.frame as isize + delta).rem_euclid(count) as usize;
+                playback.paused = true;
+            }
+        }
+    }
+
+    /// The frame shown of `image_path`, the first one unless it's the current
+    /// image.
+    pub fn frame(&self, image_path: &Path) -> usize {
+        match &self.playback {
+            Some(playback) if playback.image_path == image_path => playback.frame,
+            _ => 0,
+        }
+    }
+
+    /// The frame shown, how many there are, and whether the animation is
+    /// paused, if the current image is animated.
+    pub fn playback_state(&self) -> Option<(usize, usize, bool)> {
+        let playback = self.playback.as_ref()?;
+        let animation = playback.animation.as_ref()?;
+        Some((playback.frame, animation.frame_count(), playback.paused))
+    }
+
+    pub fn report_error(&mut self, err: anyhow::Error) {
+        self.last_error = Some((err.to_string(), Instant::now()));
+    }
+
+    pub fn saved_recently(&self) -> bool {
+        self.last_save
+            .is_some_and(|last_save| last_save.elapsed() < STATUS_DURATION)
+    }
+
+    pub fn recent_error(&self) -> Option<&str> {
+        match &self.last_error {
+            Some((error, last_error)) if last_error.elapsed() < STATUS_DURATION => Some(error),
+            _ => None,
+        }
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            tab: self.tab,
+            script_offset: self.script_offset,
+            current: self.current,
+            zoom: self.zoom,
+            pinned: self.pinned.clone(),
+            compare_next: self.compare_next,
+            focus: self.focus,
+            actions: self.actions.len(),
+            last_action: self.actions.last().cloned(),
+            enable_input: self.enable_input,
+            input: self.input.clone(),
+            input_idx: self.input_idx,
+            grid_cursor: self.grid_cursor,
+            grid_selection: self.grid_selection.clone(),
+            grid_anchor: self.grid_anchor,
+            frame: self.playback.as_ref().map_or(0, |playback| playback.frame),
+            frame_count: self.playback_state().map_or(0, |(_, count, _)| count),
+            paused: self
+                .playback
+                .as_ref()
+                .is_some_and(|playback| playback.paused),
+            exit_prompt: self.exit_prompt_left().map(|left| left.as_secs()),
+            show_histogram: self.show_histogram,
+            histogram_ready: self.current_histogram().is_some(),
+            show_all_controls: self.show_all_controls,
+            saved_recently: self.saved_recently(),
+            recent_error: self.recent_error().map(str::to_string),
+        }
+    }
+
     pub fn parse_key_mapping(
         args: Vec<(char, PathBuf)>,
     ) -> Result<(BTreeMap<char, PathBuf>, Vec<Action>)> {
@@ -209,11 +758,17 @@ impl App {
         Ok((key_mapping, actions))
     }
 
-    pub fn parse_images(args: Vec<PathBuf>, recurse: bool) -> Vec<PathBuf> {
+    pub fn parse_images(args: Vec<PathBuf>, recurse: bool, filter: &ImageFilter) -> Vec<PathBuf> {
         let mut images: Vec<PathBuf> = vec![];
 
         for input in args.into_iter() {
-            images.extend(App::discover_images(input.as_path(), recurse, true, &mut 0));
+            images.extend(App::discover_images(
+                input.as_path(),
+                recurse,
+                filter,
+                true,
+                &mut 0,
+            ));
         }
 
         images
@@ -222,6 +777,7 @@ impl App {
     fn discover_images(
         path: &Path,
         recurse: bool,
+        filter: &ImageFilter,
         is_first: bool,
         parent_count: &mut u16,
     ) -> Vec<PathBuf> {
@@ -247,8 +803,14 @@ impl App {
             let path = entry.path();
 
             if path.is_dir() && (is_first || recurse) {
-                images.extend(App::discover_images(&path, recurse, false, parent_count));
-            } else if App::is_image(path.as_path()) {
+                images.extend(App::discover_images(
+                    &path,
+                    recurse,
+                    filter,
+                    false,
+                    parent_count,
+                ));
+            } else if filter.matches(path.as_path()) {
                 *parent_count += 1;
                 images.push(path);
             }
@@ -256,30 +818,68 @@ impl App {
 
         images
     }
+}
 
-    fn is_image(file: &Path) -> bool {
-        // first, a quick check for the file extension
-        let image_exts = ["jpeg", "jpg", "png"];
-        let looks_like_image = file.extension().map_or(false, |f| {
-            image_exts.iter().any(|ext| f.to_str() == Some(ext))
-        });
-        if !looks_like_image {
-            return false;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // second, check the file's mime type by reading the first few bytes
-        let kind = infer::get_from_path(file);
-        if kind.is_err() {
-            // could not read file
-            return false;
-        }
-        let kind = kind.unwrap();
-        if kind.is_none() {
-            // unknown file type
-            return false;
+    fn app(images: &[&str]) -> App {
+        App {
+            images: images.iter().map(PathBuf::from).collect(),
+            ..App::default()
         }
+    }
+
+    #[test]
+    fn selects_a_range_in_the_grid() {
+        let mut app = app(&["a.png", "b.png", "c.png"]);
+        app.grid_toggle_range();
+        app.grid_move(2);
+        app.grid_toggle_range();
+
+        assert_eq!(app.grid_selection.len(), 3);
+        assert_eq!(app.grid_anchor, None);
+    }
+
+    #[test]
+    fn ends_a_range_after_everything_is_sorted() {
+        let mut app = app(&["a.png", "b.png"]);
+        app.grid_toggle_range();
+        app.push_action(Action::Skip(PathBuf::from("a.png")));
+        app.push_action(Action::Skip(PathBuf::from("b.png")));
+        app.grid_toggle_range();
+
+        assert!(app.grid_selection.is_empty());
+        assert_eq!(app.grid_anchor, None);
+    }
+
+    #[test]
+    fn renames_in_the_main_tab() {
+        let mut app = app(&["a.png"]);
+        app.tab = TABS.iter().position(|&tab| tab == TabId::Grid).unwrap();
+        app.rename_current_image();
+
+        assert!(app.enable_input);
+        assert!(app.current_tab() == TabId::Main);
+    }
+
+    #[test]
+    fn keeps_the_histogram_of_the_current_image() {
+        let mut app = app(&["a.png", "b.png"]);
+        assert_eq!(app.sync_histogram(), None);
+
+        app.toggle_histogram();
+        assert_eq!(app.sync_histogram(), Some(PathBuf::from("a.png")));
+        assert_eq!(app.sync_histogram(), None);
+
+        // computed for an image the user moved past
+        app.set_histogram(Path::new("b.png"), Err(anyhow!("too late")));
+        assert!(app.current_histogram().is_none());
 
-        let kind = kind.unwrap();
-        matches!(kind.mime_type(), "image/jpeg" | "image/png")
+        app.set_histogram(Path::new("a.png"), Err(anyhow!("broken")));
+        assert!(app
+            .current_histogram()
+            .is_some_and(|histogram| histogram.is_err()));
     }
 }
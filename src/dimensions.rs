@@ -0,0 +1,481 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::format::Format;
+use crate::raw;
+use crate::svg;
+
+/// Image dimensions read from the file headers, cached per path for as long
+/// as the file isn't modified.
+#[derive(Default)]
+pub struct DimensionsCache {
+    entries: HashMap<PathBuf, (SystemTime, (u32, u32))>,
+}
+
+impl DimensionsCache {
+    pub fn get(&mut self, path: &Path) -> Result<(u32, u32)> {
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some((mtime, dimensions)) = self.entries.get(path) {
+            if *mtime == modified {
+                return Ok(*dimensions);
+            }
+        }
+
+        let dimensions = read_dimensions(path)?;
+        self.entries
+            .insert(path.to_path_buf(), (modified, dimensions));
+        Ok(dimensions)
+    }
+}
+
+/// Read the width and height of an image from its headers, without decoding
+/// it. Supports JPEG, PNG, GIF, WebP, BMP, TIFF, HEIF, AVIF, camera RAW and
+/// SVG, which is parsed.
+pub fn read_dimensions(path: &Path) -> Result<(u32, u32)> {
+    match Format::from_extension(path) {
+        // RAW files are shown by their largest preview
+        Some(Format::Raw) => return raw::preview_dimensions(path),
+        Some(Format::Svg) => return svg::dimensions(path),
+        _ => {}
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 12];
+    reader.read_exact(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let dimensions = match magic {
+        [0x89, b'P', b'N', b'G', ..] => png_dimensions(&mut reader)?,
+        [0xff, 0xd8, ..] => jpeg_dimensions(&mut reader)?,
+        [b'G', b'I', b'F', b'8', ..] => gif_dimensions(&mut reader)?,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P'] => {
+            webp_dimensions(&mut reader)?
+        }
+        [b'B', b'M', ..] => bmp_dimensions(&mut reader)?,
+        [b'I', b'I', 42, 0, ..] => tiff_dimensions(&mut reader, false)?,
+        [b'M', b'M', 0, 42, ..] => tiff_dimensions(&mut reader, true)?,
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => heif_dimensions(&mut reader)?,
+        _ => return Err(anyhow!("unknown image format: {}", path.display())),
+    };
+
+    if dimensions.0 == 0 || dimensions.1 == 0 {
+        return Err(anyhow!("image without pixels: {}", path.display()));
+    }
+    Ok(dimensions)
+}
+
+pub fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn png_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    // signature (8 bytes), then the IHDR chunk: length, type, width, height
+    reader.seek(SeekFrom::Start(16))?;
+    let width = u32::from_be_bytes(read_bytes(reader)?);
+    let height = u32::from_be_bytes(read_bytes(reader)?);
+    Ok((width, height))
+}
+
+fn gif_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    // "GIF87a" or "GIF89a", then the logical screen width and height
+    reader.seek(SeekFrom::Start(6))?;
+    let width = u16::from_le_bytes(read_bytes(reader)?);
+    let height = u16::from_le_bytes(read_bytes(reader)?);
+    Ok((width as u32, height as u32))
+}
+
+fn bmp_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    reader.seek(SeekFrom::Start(14))?;
+    let header_size = u32::from_le_bytes(read_bytes(reader)?);
+    if header_size == 12 {
+        // BITMAPCOREHEADER, from OS/2
+        let width = u16::from_le_bytes(read_bytes(reader)?);
+        let height = u16::from_le_bytes(read_bytes(reader)?);
+        return Ok((width as u32, height as u32));
+    }
+
+    // the height is negative for top-down bitmaps
+    let width = i32::from_le_bytes(read_bytes(reader)?);
+    let height = i32::from_le_bytes(read_bytes(reader)?);
+    Ok((width.unsigned_abs(), height.unsigned_abs()))
+}
+
+fn jpeg_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    Ok(jpeg_frame(reader)?.1)
+}
+
+/// The start of frame marker of the JPEG at the reader's position, which
+/// tells how it's compressed, and its dimensions.
+pub fn jpeg_frame<R: Read + Seek>(reader: &mut R) -> Result<(u8, (u32, u32))> {
+    if read_bytes(reader)? != [0xff, 0xd8] {
+        return Err(anyhow!("not a JPEG"));
+    }
+    loop {
+        let [marker_start, mut marker] = read_bytes(reader)?;
+        if marker_start != 0xff {
+            return Err(anyhow!("invalid JPEG marker"));
+        }
+        // markers may be padded with any number of 0xff
+        while marker == 0xff {
+            [marker] = read_bytes(reader)?;
+        }
+
+        match marker {
+            // markers without a segment
+            0x01 | 0xd0..=0xd7 => continue,
+            0xd9 | 0xda => return Err(anyhow!("no frame header in JPEG")),
+            _ => {}
+        }
+
+        let length = u16::from_be_bytes(read_bytes(reader)?);
+        match marker {
+            // start of frame, except DHT, JPG and DAC which share the range
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let [_precision] = read_bytes(reader)?;
+                let height = u16::from_be_bytes(read_bytes(reader)?);
+                let width = u16::from_be_bytes(read_bytes(reader)?);
+                return Ok((marker, (width as u32, height as u32)));
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64 - 2))?;
+            }
+        }
+    }
+}
+
+fn webp_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    // "RIFF", file size, "WEBP", then the first chunk
+    reader.seek(SeekFrom::Start(12))?;
+    let chunk: [u8; 4] = read_bytes(reader)?;
+    let _chunk_size: [u8; 4] = read_bytes(reader)?;
+
+    match &chunk {
+        b"VP8 " => {
+            // frame tag (3 bytes) and start code (3 bytes), then 14 bits
+            // for each dimension
+            reader.seek(SeekFrom::Current(6))?;
+            let width = u16::from_le_bytes(read_bytes(reader)?) & 0x3fff;
+            let height = u16::from_le_bytes(read_bytes(reader)?) & 0x3fff;
+            Ok((width as u32, height as u32))
+        }
+        b"VP8L" => {
+            // signature byte, then 14 bits for each dimension minus one
+            let [_signature] = read_bytes(reader)?;
+            let bits = u32::from_le_bytes(read_bytes(reader)?);
+            Ok(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => {
+            // flags (4 bytes), then 24 bits for each dimension minus one
+            reader.seek(SeekFrom::Current(4))?;
+            let [w0, w1, w2, h0, h1, h2] = read_bytes(reader)?;
+            let width = u32::from_le_bytes([w0, w1, w2, 0]) + 1;
+            let height = u32::from_le_bytes([h0, h1, h2, 0]) + 1;
+            Ok((width, height))
+        }
+        _ => Err(anyhow!("unknown WebP chunk")),
+    }
+}
+
+fn tiff_dimensions<R: Read + Seek>(reader: &mut R, big_endian: bool) -> Result<(u32, u32)> {
+    let u16_from = |bytes| {
+        if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    };
+    let u32_from = |bytes| {
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+
+    // byte order and magic number, then the offset of the first IFD
+    reader.seek(SeekFrom::Start(4))?;
+    let ifd_offset = u32_from(read_bytes(reader)?);
+    reader.seek(SeekFrom::Start(ifd_offset as u64))?;
+
+    let (mut width, mut height) = (None, None);
+    let entries = u16_from(read_bytes(reader)?);
+    for _ in 0..entries {
+        let tag = u16_from(read_bytes(reader)?);
+        let field_type = u16_from(read_bytes(reader)?);
+        let _count: [u8; 4] = read_bytes(reader)?;
+        let [a, b, c, d] = read_bytes(reader)?;
+        // SHORT (3) or LONG (4), left-justified in the value field
+        let value = match field_type {
+            3 => u16_from([a, b]) as u32,
+            4 => u32_from([a, b, c, d]),
+            _ => continue,
+        };
+        match tag {
+            256 => width = Some(value),
+            257 => height = Some(value),
+            _ => {}
+        }
+    }
+
+    match (width, height) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(anyhow!("no dimensions in TIFF")),
+    }
+}
+
+/// The type, and where the contents start and end, of every box between
+/// `start` and `end` of an ISO base media file, e.g. HEIF, AVIF or CR3.
+pub fn boxes<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+) -> Result<Vec<([u8; 4], u64, u64)>> {
+    let mut boxes = vec![];
+    let mut offset = start;
+    while end.saturating_sub(offset) >= 8 {
+        reader.seek(SeekFrom::Start(offset))?;
+        let size = u32::from_be_bytes(read_bytes(reader)?) as u64;
+        let box_type = read_bytes(reader)?;
+        let (header_size, size) = match size {
+            // up to the end of the file
+            0 => (8, end - offset),
+            // a 64 bits size follows the type
+            1 => (16, u64::from_be_bytes(read_bytes(reader)?)),
+            _ => (8, size),
+        };
+        if size < header_size {
+            return Err(anyhow!("invalid HEIF box size"));
+        }
+
+        let box_end = offset
+            .checked_add(size)
+            .ok_or_else(|| anyhow!("invalid HEIF box size"))?;
+        boxes.push((box_type, offset + header_size, box_end.min(end)));
+        offset = box_end;
+    }
+    Ok(boxes)
+}
+
+/// A box of `box_type` holding `contents`, to build HEIF and CR3 files in
+/// tests.
+#[cfg(test)]
+pub fn iso_box(box_type: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let size = (8 + contents.len()) as u32;
+    [&size.to_be_bytes()[..], box_type, contents].concat()
+}
+
+pub fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    box_type: &[u8; 4],
+) -> Result<(u64, u64)> {
+    boxes(reader, start, end)?
+        .into_iter()
+        .find(|(found, _, _)| found == box_type)
+        .map(|(_, start, end)| (start, end))
+        .ok_or_else(|| anyhow!("no {} box in HEIF", String::from_utf8_lossy(box_type)))
+}
+
+fn heif_dimensions<R: Read + Seek>(reader: &mut R) -> Result<(u32, u32)> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    // meta, pitm, ipma and ispe are full boxes, starting with a version and
+    // flags
+    let (meta_start, meta_end) = find_box(reader, 0, file_end, b"meta")?;
+    let meta_start = meta_start + 4;
+
+    // the primary item is the image shown, next to its thumbnails, or the
+    // tiles of a grid
+    let (pitm_start, _) = find_box(reader, meta_start, meta_end, b"pitm")?;
+    reader.seek(SeekFrom::Start(pitm_start))?;
+    let [version, _, _, _] = read_bytes(reader)?;
+    let primary_item = if version == 0 {
+        u16::from_be_bytes(read_bytes(reader)?) as u32
+    } else {
+        u32::from_be_bytes(read_bytes(reader)?)
+    };
+
+    let (iprp_start, iprp_end) = find_box(reader, meta_start, meta_end, b"iprp")?;
+    let (ipco_start, ipco_end) = find_box(reader, iprp_start, iprp_end, b"ipco")?;
+    let properties = boxes(reader, ipco_start, ipco_end)?;
+
+    // which of the properties in ipco belong to each item, counting from 1
+    let (ipma_start, _) = find_box(reader, iprp_start, iprp_end, b"ipma")?;
+    reader.seek(SeekFrom::Start(ipma_start))?;
+    let [version, _, _, flags] = read_bytes(reader)?;
+    let mut primary_properties = vec![];
+    for _ in 0..u32::from_be_bytes(read_bytes(reader)?) {
+        let item = if version == 0 {
+            u16::from_be_bytes(read_bytes(reader)?) as u32
+        } else {
+            u32::from_be_bytes(read_bytes(reader)?)
+        };
+        let [count] = read_bytes(reader)?;
+        for _ in 0..count {
+            // the highest bit tells whether the property is essential
+            let index = if flags & 1 == 1 {
+                (u16::from_be_bytes(read_bytes(reader)?) & 0x7fff) as usize
+            } else {
+                let [index] = read_bytes(reader)?;
+                (index & 0x7f) as usize
+            };
+            if item == primary_item {
+                primary_properties.push(index);
+            }
+        }
+    }
+
+    let (mut dimensions, mut rotated) = (None, false);
+    for index in primary_properties {
+        let Some(&(box_type, start, _)) = index.checked_sub(1).and_then(|i| properties.get(i))
+        else {
+            continue;
+        };
+        reader.seek(SeekFrom::Start(start))?;
+        match &box_type {
+            b"ispe" => {
+                let _version_and_flags: [u8; 4] = read_bytes(reader)?;
+                let width = u32::from_be_bytes(read_bytes(reader)?);
+                let height = u32::from_be_bytes(read_bytes(reader)?);
+                dimensions = Some((width, height));
+            }
+            b"irot" => {
+                // counterclockwise, in quarter turns
+                let [angle] = read_bytes(reader)?;
+                rotated = angle & 1 == 1;
+            }
+            _ => {}
+        }
+    }
+
+    // the image is stored as encoded, and turned when decoded
+    match dimensions {
+        Some((width, height)) if rotated => Ok((height, width)),
+        Some(dimensions) => Ok(dimensions),
+        None => Err(anyhow!("no dimensions in HEIF")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_png_ihdr() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(png_dimensions(&mut Cursor::new(png)).unwrap(), (640, 480));
+    }
+
+    #[test]
+    fn reads_gif_screen_size() {
+        let gif = b"GIF89a\x80\x02\xe0\x01".to_vec();
+        assert_eq!(gif_dimensions(&mut Cursor::new(gif)).unwrap(), (640, 480));
+    }
+
+    #[test]
+    fn reads_jpeg_sof() {
+        let jpeg = [
+            &[0xff, 0xd8][..],
+            // APP0, skipped
+            &[0xff, 0xe0, 0, 6, b'J', b'F', b'I', b'F'],
+            // DHT, which isn't a start of frame even though it's in the range
+            &[0xff, 0xc4, 0, 4, 0, 0],
+            // padded progressive SOF: precision, height, width
+            &[0xff, 0xff, 0xc2, 0, 11, 8, 0x01, 0xe0, 0x02, 0x80],
+        ]
+        .concat();
+        assert_eq!(
+            jpeg_frame(&mut Cursor::new(jpeg)).unwrap(),
+            (0xc2, (640, 480))
+        );
+    }
+
+    #[test]
+    fn rejects_jpeg_without_sof() {
+        let jpeg = [0xff, 0xd8, 0xff, 0xda, 0, 2];
+        assert!(jpeg_dimensions(&mut Cursor::new(jpeg)).is_err());
+    }
+
+    fn webp(chunk: &[u8], data: &[u8]) -> Cursor<Vec<u8>> {
+        Cursor::new([b"RIFF\0\0\0\0WEBP", chunk, b"\0\0\0\0", data].concat())
+    }
+
+    #[test]
+    fn reads_webp_headers() {
+        // lossy: frame tag, start code, then 14 bits for each dimension
+        let mut lossy = webp(
+            b"VP8 ",
+            &[0, 0, 0, 0x9d, 0x01, 0x2a, 0x80, 0x02, 0xe0, 0x01],
+        );
+        assert_eq!(webp_dimensions(&mut lossy).unwrap(), (640, 480));
+
+        // lossless: signature, then 14 bits for each dimension minus one
+        let bits: u32 = 639 | (479 << 14);
+        let mut lossless = webp(b"VP8L", &[&[0x2f][..], &bits.to_le_bytes()].concat());
+        assert_eq!(webp_dimensions(&mut lossless).unwrap(), (640, 480));
+
+        // extended: flags, then 24 bits for each dimension minus one
+        let mut extended = webp(b"VP8X", &[0, 0, 0, 0, 0x7f, 0x02, 0, 0xdf, 0x01, 0]);
+        assert_eq!(webp_dimensions(&mut extended).unwrap(), (640, 480));
+    }
+
+    #[test]
+    fn rejects_boxes_past_the_largest_offset() {
+        let mut file = iso_box(b"free", &[]);
+        file.extend_from_slice(
+            &[&1u32.to_be_bytes()[..], b"mdat", &u64::MAX.to_be_bytes()].concat(),
+        );
+        let end = file.len() as u64;
+        assert!(boxes(&mut Cursor::new(file), 0, end).is_err());
+    }
+
+    #[test]
+    fn reads_heif_primary_item_turned() {
+        let ispe = |width: u32, height: u32| {
+            iso_box(
+                b"ispe",
+                &[[0; 4], width.to_be_bytes(), height.to_be_bytes()].concat(),
+            )
+        };
+        let ipco = iso_box(
+            b"ipco",
+            &[
+                ispe(640, 480),
+                // a quarter turn
+                iso_box(b"irot", &[1]),
+                ispe(160, 120),
+            ]
+            .concat(),
+        );
+        // the primary item (1) has the first two properties, the essential
+        // bit set on the rotation, its thumbnail (2) the third one
+        let ipma = iso_box(
+            b"ipma",
+            &[0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 2, 1, 0x82, 0, 2, 1, 3],
+        );
+        let meta = iso_box(
+            b"meta",
+            &[
+                &[0; 4][..],
+                &iso_box(b"pitm", &[0, 0, 0, 0, 0, 1]),
+                &iso_box(b"iprp", &[ipco, ipma].concat()),
+            ]
+            .concat(),
+        );
+        let heif = [iso_box(b"ftyp", b"heic\0\0\0\0mif1heic"), meta].concat();
+
+        let dimensions = heif_dimensions(&mut Cursor::new(heif)).unwrap();
+        assert_eq!(dimensions, (480, 640));
+    }
+}
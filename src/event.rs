@@ -1,36 +1,102 @@
 use anyhow::{anyhow, Result};
-use crossbeam_channel::{unbounded, Receiver};
-use std::{io, thread, time::Duration};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use image::DynamicImage;
+use signal_hook::{
+    consts::{SIGHUP, SIGTERM, SIGWINCH},
+    iterator::Signals,
+};
+use std::{cell::Cell, io, path::PathBuf, sync::Arc, thread, time::Duration};
 use termion::{event::Key, input::TermRead};
 
+use crate::animation::Animation;
+use crate::histogram::Histogram;
+use crate::image_display::ImageKey;
+
 pub enum Event {
     Input(Key),
     Tick,
+    /// The terminal window changed size
+    Resize,
+    /// The app was asked to quit by `signal`, SIGTERM or SIGHUP
+    Terminate(i32),
+    /// An image decoded ahead of time by the `Prefetcher`, or why it couldn't
+    /// be
+    Prefetched(ImageKey, Result<DynamicImage>),
+    /// The frames of an image, decoded by the `Prefetcher`, `None` if it's
+    /// not animated
+    Animation(PathBuf, Result<Option<Arc<Animation>>>),
+    /// The histogram of an image, computed by the `Prefetcher`
+    Histogram(PathBuf, Result<Arc<Histogram>>),
 }
 
 pub struct EventsListener {
+    tx: Sender<Event>,
     rx: Receiver<Event>,
+    tick_rate: Cell<Duration>,
+    tick_rate_tx: Sender<Duration>,
 }
 
 impl EventsListener {
-    pub fn new(tick_rate: Duration) -> Self {
+    pub fn new(tick_rate: Duration) -> Result<Self> {
         let (tx, rx) = unbounded::<Event>();
+        let tx_keys = tx.clone();
+        let tx_signals = tx.clone();
         let tx_clone = tx.clone();
 
         thread::spawn(move || {
             let stdin = io::stdin();
             for key in stdin.keys().flatten() {
-                tx.send(Event::Input(key)).unwrap();
+                tx_keys.send(Event::Input(key)).unwrap();
             }
         });
-        thread::spawn(move || loop {
-            if tx_clone.send(Event::Tick).is_err() {
-                break;
+        let mut signals = Signals::new([SIGWINCH, SIGTERM, SIGHUP])?;
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                let event = match signal {
+                    SIGWINCH => Event::Resize,
+                    signal => Event::Terminate(signal),
+                };
+                if tx_signals.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (tick_rate_tx, tick_rate_rx) = unbounded::<Duration>();
+        thread::spawn(move || {
+            let mut tick_rate = tick_rate;
+            loop {
+                match tick_rate_rx.recv_timeout(tick_rate) {
+                    Ok(new_tick_rate) => tick_rate = new_tick_rate,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if tx_clone.send(Event::Tick).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
             }
-            thread::sleep(tick_rate);
         });
 
-        EventsListener { rx }
+        Ok(EventsListener {
+            tx,
+            rx,
+            tick_rate: Cell::new(tick_rate),
+            tick_rate_tx,
+        })
+    }
+
+    /// Change how long until the next tick, and between the ones after it.
+    pub fn set_tick_rate(&self, tick_rate: Duration) {
+        if self.tick_rate.replace(tick_rate) != tick_rate {
+            // the ticks stop only when the main loop is gone
+            let _ = self.tick_rate_tx.send(tick_rate);
+        }
+    }
+
+    /// For other threads to send their own events to the main loop.
+    pub fn sender(&self) -> Sender<Event> {
+        self.tx.clone()
     }
 
     pub fn next(&self) -> Result<Event> {
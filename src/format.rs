@@ -0,0 +1,352 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::raw;
+use crate::svg;
+
+/// Formats sniffed by `Format::sniff`, and when their file was modified.
+type Sniffed = HashMap<PathBuf, (SystemTime, Option<Format>)>;
+
+static SNIFFED: OnceLock<Mutex<Sniffed>> = OnceLock::new();
+
+/// The image formats which can be sorted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Avif,
+    Bmp,
+    Gif,
+    Heif,
+    Jpeg,
+    Png,
+    Raw,
+    Svg,
+    Tiff,
+    WebP,
+}
+
+impl Format {
+    pub const ALL: [Format; 10] = [
+        Format::Avif,
+        Format::Bmp,
+        Format::Gif,
+        Format::Heif,
+        Format::Jpeg,
+        Format::Png,
+        Format::Raw,
+        Format::Svg,
+        Format::Tiff,
+        Format::WebP,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Avif => "AVIF",
+            Format::Bmp => "BMP",
+            Format::Gif => "GIF",
+            Format::Heif => "HEIF",
+            Format::Jpeg => "JPEG",
+            Format::Png => "PNG",
+            Format::Raw => "RAW",
+            Format::Svg => "SVG",
+            Format::Tiff => "TIFF",
+            Format::WebP => "WebP",
+        }
+    }
+
+    /// File extensions of the format, in lowercase.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Avif => &["avif"],
+            Format::Bmp => &["bmp"],
+            Format::Gif => &["gif"],
+            Format::Heif => &["heic", "heif"],
+            Format::Jpeg => &["jpeg", "jpg"],
+            Format::Png => &["png"],
+            Format::Raw => &["arw", "cr2", "cr3", "dng", "nef", "orf", "raf"],
+            Format::Svg => &["svg", "svgz"],
+            Format::Tiff => &["tif", "tiff"],
+            Format::WebP => &["webp"],
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Avif => "image/avif",
+            Format::Bmp => "image/bmp",
+            Format::Gif => "image/gif",
+            Format::Heif => "image/heif",
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Raw => "image/x-dcraw",
+            Format::Svg => "image/svg+xml",
+            Format::Tiff => "image/tiff",
+            Format::WebP => "image/webp",
+        }
+    }
+
+    /// Whether images of the format are decoded by an external program, the
+    /// image crate can't.
+    pub fn needs_converter(self) -> bool {
+        matches!(self, Format::Avif | Format::Heif)
+    }
+
+    /// The format a file's extension stands for, whatever its case, e.g.
+    /// `IMG_0001.JPG`.
+    pub fn from_extension(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn from_mime_type(mime_type: &str) -> Option<Format> {
+        // the only RAW format with a magic number of its own
+        if mime_type == "image/x-canon-cr2" {
+            return Some(Format::Raw);
+        }
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| format.mime_type() == mime_type)
+    }
+
+    /// The format of a file, by reading its first few bytes. It's looked up
+    /// on every frame, so it's cached per path for as long as the file isn't
+    /// modified.
+    pub fn sniff(path: &Path) -> Option<Format> {
+        let Ok(modified) = fs::metadata(path).and_then(|metadata| metadata.modified()) else {
+            return Format::read(path);
+        };
+        let sniffed = SNIFFED.get_or_init(Default::default);
+        let cached = sniffed
+            .lock()
+            .ok()
+            .and_then(|sniffed| sniffed.get(path).copied());
+        if let Some((mtime, format)) = cached {
+            if mtime == modified {
+                return format;
+            }
+        }
+
+        let format = Format::read(path);
+        if let Ok(mut sniffed) = sniffed.lock() {
+            sniffed.insert(path.to_path_buf(), (modified, format));
+        }
+        format
+    }
+
+    fn read(path: &Path) -> Option<Format> {
+        // most RAW files look like TIFF, and SVG is text, they're told apart
+        // by their extension first
+        match Format::from_extension(path) {
+            Some(Format::Raw) => return raw::is_raw(path).then_some(Format::Raw),
+            Some(Format::Svg) => return svg::is_svg(path).then_some(Format::Svg),
+            _ => {}
+        }
+
+        Format::from_content(path)
+    }
+
+    /// The format of a file by its magic number alone, whatever its name.
+    /// SVG and RAW files other than CR2 aren't recognized.
+    pub fn from_content(path: &Path) -> Option<Format> {
+        let kind = infer::get_from_path(path).ok()??;
+        Format::from_mime_type(kind.mime_type())
+    }
+
+    /// Which files of the format `--sniff` finds.
+    pub fn found_by_content(self) -> &'static str {
+        match self {
+            Format::Raw => "CR2 only",
+            Format::Svg => "no",
+            _ => "yes",
+        }
+    }
+
+    /// Whether `--sniff` finds any file of the format.
+    fn has_magic_number(self) -> bool {
+        self != Format::Svg
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    /// A format by its name or one of its extensions, e.g. `jpeg` or `jpg`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_ascii_lowercase();
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| {
+                format.name().to_ascii_lowercase() == s || format.extensions().contains(&s.as_str())
+            })
+            .ok_or_else(|| anyhow!("unknown image type `{}`, see --list-types", s))
+    }
+}
+
+/// Which files are taken for images to sort, by `--types`, `--ext` and
+/// `--sniff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageFilter {
+    types: Vec<Format>,
+    // lowercase, all those of `types` when empty
+    extensions: Vec<String>,
+    sniff: bool,
+}
+
+impl ImageFilter {
+    /// No `types` and no `extensions` take any format there is. Types which
+    /// can't be told by their contents are refused with `sniff`, they'd never
+    /// be found.
+    pub fn new(types: Vec<Format>, extensions: Vec<String>, sniff: bool) -> Result<Self> {
+        if let Some(format) = types
+            .iter()
+            .find(|format| sniff && !format.has_magic_number())
+        {
+            return Err(anyhow!(
+                "{} images can't be found with --sniff, by their contents alone",
+                format.name()
+            ));
+        }
+
+        let types = if types.is_empty() {
+            Format::ALL.to_vec()
+        } else {
+            types
+        };
+        let extensions = extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+            .collect();
+        Ok(ImageFilter {
+            types,
+            extensions,
+            sniff,
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        // files saved without an extension, or with a wrong one, are only
+        // found by their contents
+        if self.sniff {
+            return Format::from_content(path).is_some_and(|format| self.types.contains(&format));
+        }
+
+        // first, a quick check for the file extension
+        let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+            return false;
+        };
+        let extension = extension.to_ascii_lowercase();
+        let eligible = if self.extensions.is_empty() {
+            self.types
+                .iter()
+                .any(|format| format.extensions().contains(&extension.as_str()))
+        } else {
+            self.extensions.contains(&extension)
+        };
+        if !eligible {
+            return false;
+        }
+
+        // second, check the file's format by reading the first few bytes
+        Format::sniff(path).is_some_and(|format| self.types.contains(&format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf, process};
+
+    /// A folder of its own for each test, with `files` in it.
+    fn folder(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (file_name, contents) in files {
+            fs::write(dir.join(file_name), contents).unwrap();
+        }
+        dir
+    }
+
+    const JPEG: &[u8] = include_bytes!("../tests/fixtures/gradient.jpg");
+    const PNG: &[u8] = include_bytes!("../tests/fixtures/quadrants.png");
+    const SVG: &[u8] = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1\" height=\"1\"/>";
+
+    fn matching(dir: &Path, filter: &ImageFilter) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| filter.matches(path))
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn matches_extension_and_contents() {
+        let dir = folder(
+            "filter",
+            &[
+                ("photo.JPG", JPEG),
+                ("photo.jfif", JPEG),
+                ("photo", JPEG),
+                ("screenshot.png", PNG),
+                ("renamed.png", JPEG),
+                ("notes.png", b"not an image"),
+                ("icon.svg", SVG),
+            ],
+        );
+
+        let filter = ImageFilter::new(vec![], vec![], false).unwrap();
+        assert_eq!(
+            matching(&dir, &filter),
+            ["icon.svg", "photo.JPG", "renamed.png", "screenshot.png"]
+        );
+
+        // the extension must be one of the type's too
+        let filter = ImageFilter::new(vec![Format::Jpeg], vec![], false).unwrap();
+        assert_eq!(matching(&dir, &filter), ["photo.JPG"]);
+
+        let filter = ImageFilter::new(vec![], vec![".JFIF".to_string()], false).unwrap();
+        assert_eq!(matching(&dir, &filter), ["photo.jfif"]);
+
+        let filter = ImageFilter::new(vec![], vec![], true).unwrap();
+        assert_eq!(
+            matching(&dir, &filter),
+            [
+                "photo",
+                "photo.JPG",
+                "photo.jfif",
+                "renamed.png",
+                "screenshot.png"
+            ]
+        );
+
+        let filter = ImageFilter::new(vec![Format::Png], vec![], true).unwrap();
+        assert_eq!(matching(&dir, &filter), ["screenshot.png"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_types_sniff_cant_find() {
+        assert!(ImageFilter::new(vec![Format::Svg], vec![], true).is_err());
+        assert!(ImageFilter::new(vec![Format::Svg], vec![], false).is_ok());
+        assert!(ImageFilter::new(vec![Format::Raw], vec![], true).is_ok());
+    }
+}
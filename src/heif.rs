@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+use std::{
+    env, fs,
+    io::{self, ErrorKind, Read},
+    path::Path,
+    process::{self, Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::format::Format;
+
+/// Programs converting HEIF and AVIF images to PNG, all called as
+/// `program input output`, in the order they're tried. libheif's tools are
+/// named heif-convert before version 1.17.
+const CONVERTERS: [(&str, &[Format]); 4] = [
+    ("heif-dec", &[Format::Heif, Format::Avif]),
+    ("heif-convert", &[Format::Heif, Format::Avif]),
+    ("avifdec", &[Format::Avif]),
+    ("magick", &[Format::Heif, Format::Avif]),
+];
+
+// A broken file could keep a converter busy forever, it's killed after this
+// long. Large images take a couple of seconds.
+const CONVERT_TIMEOUT: Duration = Duration::from_secs(30);
+// How often to check whether a converter is done
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+// images are decoded by the prefetcher and for the screen at the same time,
+// each conversion gets its own folder
+static CONVERSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Decode a HEIF (e.g. HEIC from iPhones) or AVIF image with the first
+/// converter which is installed and succeeds. They turn the image according
+/// to the container, so it's never rotated again from its EXIF orientation.
+pub fn decode(image_path: &Path, format: Format) -> Result<DynamicImage> {
+    let conversion = CONVERSIONS.fetch_add(1, Ordering::Relaxed);
+    let dir = env::temp_dir().join(format!(
+        "{}-{}-{}",
+        env!("CARGO_PKG_NAME"),
+        process::id(),
+        conversion
+    ));
+    fs::create_dir_all(&dir)?;
+    // some converters also write depth maps and such next to the output
+    let decoded = convert(image_path, format, &dir.join("image.png"));
+    let _ = fs::remove_dir_all(&dir);
+    decoded
+}
+
+fn convert(image_path: &Path, format: Format, output_path: &Path) -> Result<DynamicImage> {
+    // a converter may be installed but unable to decode the image, e.g.
+    // libheif built without an AV1 decoder, the next one is tried then
+    let mut failures = vec![];
+    for program in converters(format) {
+        let mut command = Command::new(program);
+        command.arg(image_path).arg(output_path);
+        let (status, stderr) = match run(&mut command, CONVERT_TIMEOUT) {
+            Ok(ran) => ran,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) if err.kind() == ErrorKind::TimedOut => {
+                failures.push(format!("{}: {}", program, err));
+                continue;
+            }
+            Err(err) => {
+                failures.push(format!("can't run {}: {}", program, err));
+                continue;
+            }
+        };
+        if !status.success() {
+            failures.push(format!("{}: {}", program, stderr.trim()));
+            continue;
+        }
+        match image::open(output_path) {
+            Ok(image) => return Ok(image),
+            Err(err) => failures.push(format!("{}: {}", program, err)),
+        }
+    }
+
+    if failures.is_empty() {
+        return Err(anyhow!(
+            "{} images need one of {} to be installed",
+            format.name(),
+            converters(format).join(", ")
+        ));
+    }
+    Err(anyhow!(
+        "can't decode {}: {}",
+        image_path.display(),
+        failures.join("; ")
+    ))
+}
+
+/// Run `command` until it exits, or kill it after `timeout`. Returns how it
+/// exited, and what it wrote to stderr.
+fn run(command: &mut Command, timeout: Duration) -> io::Result<(ExitStatus, String)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // read as it comes, the program would block on a full pipe otherwise
+    let mut stderr = child.stderr.take();
+    let reader = thread::spawn(move || {
+        let mut output = vec![];
+        if let Some(stderr) = stderr.as_mut() {
+            let _ = stderr.read_to_end(&mut output);
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                format!("took longer than {:?}", timeout),
+            ));
+        }
+        thread::sleep(WAIT_INTERVAL);
+    };
+    Ok((status, reader.join().unwrap_or_default()))
+}
+
+/// The programs which can decode images of `format`.
+pub fn converters(format: Format) -> Vec<&'static str> {
+    CONVERTERS
+        .iter()
+        .filter(|(_, formats)| formats.contains(&format))
+        .map(|(program, _)| *program)
+        .collect()
+}
+
+/// The converter used for images of `format`, if one is installed.
+pub fn installed_converter(format: Format) -> Option<&'static str> {
+    let path = env::var_os("PATH")?;
+    converters(format)
+        .into_iter()
+        .find(|program| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_converters_report() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo broken >&2; exit 1"]);
+        let (status, stderr) = run(&mut command, CONVERT_TIMEOUT).unwrap();
+        assert!(!status.success());
+        assert_eq!(stderr, "broken\n");
+    }
+
+    #[test]
+    fn kills_converters_taking_too_long() {
+        let started = Instant::now();
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let err = run(&mut command, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
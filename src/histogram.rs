@@ -0,0 +1,105 @@
+use anyhow::Result;
+use image::RgbaImage;
+use std::{path::Path, sync::Arc};
+
+use crate::app::Zoom;
+use crate::image_display::load_image;
+use crate::thumbnails;
+
+// Images are scaled down to this size before counting, which is plenty for
+// the shape of the histogram and much faster
+const SAMPLE_SIZE: u32 = 256;
+// A pixel is clipped when one of its channels is at least CLIPPED_LEVEL, and
+// crushed when all of them are at most CRUSHED_LEVEL
+const CLIPPED_LEVEL: u8 = 254;
+const CRUSHED_LEVEL: u8 = 2;
+
+/// How many pixels of an image have each level of luminance, red, green and
+/// blue.
+pub struct Histogram {
+    pub luma: [u64; 256],
+    pub red: [u64; 256],
+    pub green: [u64; 256],
+    pub blue: [u64; 256],
+    pixels: u64,
+    clipped: u64,
+    crushed: u64,
+}
+
+impl Histogram {
+    fn new(image: &RgbaImage) -> Self {
+        let mut histogram = Histogram {
+            luma: [0; 256],
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            pixels: 0,
+            clipped: 0,
+            crushed: 0,
+        };
+
+        for pixel in image.pixels() {
+            let [red, green, blue, alpha] = pixel.0;
+            if alpha == 0 {
+                continue;
+            }
+
+            // Rec. 709 luma
+            let luma = (0.2126 * red as f32 + 0.7152 * green as f32 + 0.0722 * blue as f32).round();
+            histogram.luma[luma as usize] += 1;
+            histogram.red[red as usize] += 1;
+            histogram.green[green as usize] += 1;
+            histogram.blue[blue as usize] += 1;
+
+            histogram.pixels += 1;
+            if red.max(green).max(blue) >= CLIPPED_LEVEL {
+                histogram.clipped += 1;
+            }
+            if red.max(green).max(blue) <= CRUSHED_LEVEL {
+                histogram.crushed += 1;
+            }
+        }
+
+        histogram
+    }
+
+    /// Share of pixels with blown out highlights, in percent.
+    pub fn clipped_percent(&self) -> f64 {
+        percent(self.clipped, self.pixels)
+    }
+
+    /// Share of pixels with shadows crushed to black, in percent.
+    pub fn crushed_percent(&self) -> f64 {
+        percent(self.crushed, self.pixels)
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 * 100.0 / total as f64
+}
+
+/// Add up the 256 levels of `levels` into `count` buckets, for a sparkline
+/// `count` cells wide.
+pub fn buckets(levels: &[u64; 256], count: usize) -> Vec<u64> {
+    let count = count.clamp(1, levels.len());
+    (0..count)
+        .map(|i| {
+            let start = i * levels.len() / count;
+            let end = (i + 1) * levels.len() / count;
+            levels[start..end].iter().sum()
+        })
+        .collect()
+}
+
+/// Compute the histogram of `image_path`, which decodes it, it's done by the
+/// `Prefetcher`.
+pub fn histogram(image_path: &Path) -> Result<Arc<Histogram>> {
+    let image = match thumbnails::load(image_path, SAMPLE_SIZE)? {
+        Some(thumbnail) => thumbnail,
+        None => load_image(image_path, Zoom::Fit, 0, SAMPLE_SIZE, SAMPLE_SIZE)?,
+    };
+    Ok(Arc::new(Histogram::new(&image.to_rgba8())))
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use image::RgbaImage;
+use ratatui::{buffer::Buffer, style::Color};
+use std::mem;
+
+use super::{ImageDisplay, Placement};
+
+/// Draws images with the upper half block character, in truecolor: the
+/// foreground paints the top pixel of the cell and the background the bottom
+/// one.
+///
+/// It's a plain ratatui buffer, so it works in any terminal, including over
+/// SSH and inside tmux.
+pub struct BlocksDisplay {
+    // images decoded for the previous frame, the buffer is drawn from scratch
+    // every time
+    decoded: Vec<(Placement, RgbaImage)>,
+    current: Vec<(Placement, RgbaImage)>,
+}
+
+impl BlocksDisplay {
+    pub fn new() -> Self {
+        BlocksDisplay {
+            decoded: vec![],
+            current: vec![],
+        }
+    }
+
+    /// The image of `placement`, unless it's not decoded yet.
+    fn decode(&mut self, placement: Placement) -> Result<Option<&RgbaImage>> {
+        let image = match self.decoded.iter().position(|(p, _)| *p == placement) {
+            Some(i) => self.decoded.swap_remove(i).1,
+            None => match placement.load(self.cell_pixels())? {
+                Some(image) => image.to_rgba8(),
+                None => return Ok(None),
+            },
+        };
+        self.current.push((placement, image));
+        Ok(Some(&self.current.last().unwrap().1))
+    }
+}
+
+fn pixel_color(image: &RgbaImage, x: u32, y: u32) -> Color {
+    match image.get_pixel_checked(x, y) {
+        Some(pixel) if pixel[3] >= 128 => Color::Rgb(pixel[0], pixel[1], pixel[2]),
+        _ => Color::Reset,
+    }
+}
+
+impl ImageDisplay for BlocksDisplay {
+    fn render_image(&mut self, placement: Placement, buf: &mut Buffer) -> Result<()> {
+        let block = placement.block;
+        let Some(image) = self.decode(placement)? else {
+            return Ok(());
+        };
+        let rows = image.height().div_ceil(2);
+
+        for y in 0..rows.min(block.height as u32) {
+            for x in 0..image.width().min(block.width as u32) {
+                buf.get_mut(block.x + x as u16, block.y + y as u16)
+                    .set_symbol("▀")
+                    .set_fg(pixel_color(image, x, y * 2))
+                    .set_bg(pixel_color(image, x, y * 2 + 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.decoded = mem::take(&mut self.current);
+        Ok(())
+    }
+
+    // a cell shows two pixels, one above the other
+    fn cell_pixels(&self) -> (u32, u32) {
+        (1, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_display::tests::placement;
+    use ratatui::{backend::TestBackend, layout::Rect, widgets::Widget, Terminal};
+
+    struct Image<'a>(&'a mut BlocksDisplay, Placement);
+
+    impl Widget for Image<'_> {
+        fn render(self, _area: Rect, buf: &mut Buffer) {
+            self.0.render_image(self.1, buf).unwrap();
+        }
+    }
+
+    #[test]
+    fn draws_two_pixels_per_cell() {
+        let mut terminal = Terminal::new(TestBackend::new(10, 5)).unwrap();
+        let mut display = BlocksDisplay::new();
+        // 8x8 pixels, red, green, blue and white quarters, with the top left
+        // corner transparent
+        let placement = placement("tests/fixtures/quadrants.png", Rect::new(1, 0, 8, 4));
+        terminal
+            .draw(|f| f.render_widget(Image(&mut display, placement), f.size()))
+            .unwrap();
+
+        let buf = terminal.backend().buffer();
+        let cell = |x, y| {
+            let cell = buf.get(x, y);
+            (cell.symbol.as_str(), cell.fg, cell.bg)
+        };
+        let (red, green, blue) = (
+            Color::Rgb(255, 0, 0),
+            Color::Rgb(0, 255, 0),
+            Color::Rgb(0, 0, 255),
+        );
+        let white = Color::Rgb(255, 255, 255);
+        assert_eq!(cell(1, 0), ("▀", Color::Reset, Color::Reset));
+        assert_eq!(cell(2, 0), ("▀", Color::Reset, red));
+        assert_eq!(cell(3, 0), ("▀", red, red));
+        assert_eq!(cell(8, 1), ("▀", green, green));
+        assert_eq!(cell(4, 3), ("▀", blue, blue));
+        assert_eq!(cell(5, 2), ("▀", white, white));
+        // nothing outside the block
+        assert_eq!(cell(0, 0), (" ", Color::Reset, Color::Reset));
+        assert_eq!(cell(9, 4), (" ", Color::Reset, Color::Reset));
+    }
+}
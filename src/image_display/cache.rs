@@ -0,0 +1,80 @@
+use anyhow::Error;
+use image::DynamicImage;
+use lru::LruCache;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use super::ImageKey;
+
+// Enough for a dozen images filling a 4K screen
+const CACHE_BYTES: usize = 384 * 1024 * 1024;
+
+/// Images decoded and scaled for the screen, shared by the backends and the
+/// prefetcher. The least recently used ones are dropped when they take more
+/// than `CACHE_BYTES`.
+struct DecodedImages {
+    images: LruCache<ImageKey, DynamicImage>,
+    bytes: usize,
+}
+
+static DECODED: OnceLock<Mutex<DecodedImages>> = OnceLock::new();
+
+/// Images the prefetcher failed to decode, and why. They aren't decoded again
+/// when they're shown, the error is reported instead.
+static FAILED: OnceLock<Mutex<HashMap<ImageKey, String>>> = OnceLock::new();
+
+fn decoded() -> &'static Mutex<DecodedImages> {
+    DECODED.get_or_init(|| {
+        Mutex::new(DecodedImages {
+            images: LruCache::unbounded(),
+            bytes: 0,
+        })
+    })
+}
+
+pub fn get(key: &ImageKey) -> Option<DynamicImage> {
+    decoded().lock().ok()?.images.get(key).cloned()
+}
+
+/// Whether `key` was decoded already, or failed to, without counting as a
+/// use of it.
+pub fn is_cached(key: &ImageKey) -> bool {
+    decoded()
+        .lock()
+        .is_ok_and(|decoded| decoded.images.contains(key))
+        || failure(key).is_some()
+}
+
+pub fn failure(key: &ImageKey) -> Option<String> {
+    FAILED.get()?.lock().ok()?.get(key).cloned()
+}
+
+pub fn cache_failure(key: ImageKey, err: Error) {
+    let failed = FAILED.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Ok(mut failed) = failed.lock() {
+        failed.insert(key, err.to_string());
+    }
+}
+
+pub fn cache_image(key: ImageKey, image: DynamicImage) {
+    let size = image.as_bytes().len();
+    if size > CACHE_BYTES {
+        return;
+    }
+
+    let Ok(mut decoded) = decoded().lock() else {
+        return;
+    };
+    decoded.bytes += size;
+    if let Some(replaced) = decoded.images.put(key, image) {
+        decoded.bytes -= replaced.as_bytes().len();
+    }
+    while decoded.bytes > CACHE_BYTES {
+        match decoded.images.pop_lru() {
+            Some((_, dropped)) => decoded.bytes -= dropped.as_bytes().len(),
+            None => break,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    env,
+    io::{self, Write},
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use super::{ItermDisplay, KittyDisplay, Renderer, W3mDisplay};
+
+// How long to wait for the terminal to answer a query. Terminals answer at
+// once, this is only reached over slow links, e.g. SSH, or when they don't
+// answer DA1 at all. A reply coming after it would be read as keypresses.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Primary device attributes, answered by virtually every terminal.
+const DA1: &str = "\x1b[c";
+
+// A 1x1 image query, answered with OK by terminals supporting the kitty
+// graphics protocol, see
+// https://sw.kovidgoyal.net/kitty/graphics-protocol/#querying-support-and-available-transmission-mediums
+const KITTY_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+
+// Window manipulation queries for the size of a cell, and of the text area,
+// both in pixels. Not every terminal answers the first one.
+const CELL_SIZE_QUERY: &str = "\x1b[16t";
+const TEXT_AREA_QUERY: &str = "\x1b[14t";
+
+// Attribute in the DA1 reply for Sixel graphics.
+const DA1_SIXEL: &str = "4";
+
+/// Pick the best renderer the terminal supports. The environment is checked
+/// first, then the terminal itself is queried, and at last we look for
+/// w3mimgdisplay. The terminal must be in raw mode, and nothing else may be
+/// reading stdin.
+pub fn detect() -> Renderer {
+    if KittyDisplay::is_supported() {
+        return Renderer::Kitty;
+    }
+    // WezTerm understands both protocols, but only draws kitty images when
+    // they're enabled in its config, which only the query tells
+    let wezterm = env::var("TERM_PROGRAM").is_ok_and(|program| program == "WezTerm");
+    if ItermDisplay::is_supported() && !wezterm {
+        return Renderer::Iterm;
+    }
+
+    if let Some(reply) = query(KITTY_QUERY) {
+        if reply.contains("\x1b_Gi=31;OK") {
+            return Renderer::Kitty;
+        }
+        if !wezterm && da1_attributes(&reply).contains(&DA1_SIXEL) {
+            return Renderer::Sixel;
+        }
+    }
+    if wezterm {
+        return Renderer::Iterm;
+    }
+
+    if W3mDisplay::find().is_some() {
+        return Renderer::W3m;
+    }
+
+    Renderer::Blocks
+}
+
+/// Ask the terminal for the size of a cell in pixels.
+pub fn query_cell_size() -> Option<(u32, u32)> {
+    let reply = query(&format!("{}{}", CELL_SIZE_QUERY, TEXT_AREA_QUERY))?;
+    if let Some((height, width)) = window_reply(&reply, "6") {
+        return Some((width, height));
+    }
+
+    let (height, width) = window_reply(&reply, "4")?;
+    let (columns, rows) = termion::terminal_size().ok()?;
+    if columns == 0 || rows == 0 {
+        return None;
+    }
+    Some((width / columns as u32, height / rows as u32))
+}
+
+/// Write `request` to the terminal, followed by a DA1 query, and return
+/// everything it answered. Terminals don't answer requests they don't
+/// understand, but they do answer DA1, which marks the end of the reply.
+pub fn query(request: &str) -> Option<String> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    if !termion::is_tty(&stdin) || !termion::is_tty(&stdout) {
+        return None;
+    }
+
+    write!(stdout, "{}{}", request, DA1).ok()?;
+    stdout.flush().ok()?;
+
+    let fd = stdin.as_raw_fd();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = vec![];
+    loop {
+        let timeout = deadline
+            .checked_duration_since(Instant::now())
+            .unwrap_or_default();
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Read straight from the file descriptor, going through `Stdin` would
+        // block once the terminal is done answering.
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            // drop the part of the reply which came in time, so the events
+            // listener doesn't take it for keypresses
+            unsafe { libc::tcflush(fd, libc::TCIFLUSH) };
+            return None;
+        }
+
+        let mut buf = [0u8; 256];
+        let read = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if read <= 0 {
+            return None;
+        }
+        reply.extend_from_slice(&buf[..read as usize]);
+
+        let reply = String::from_utf8_lossy(&reply);
+        if da1_reply(&reply).is_some() {
+            return Some(reply.into_owned());
+        }
+    }
+}
+
+/// The DA1 reply in `reply`, i.e. `\x1b[?62;4;22c`, without the `c`.
+fn da1_reply(reply: &str) -> Option<&str> {
+    let start = reply.rfind("\x1b[?")? + 3;
+    let len = reply[start..].find('c')?;
+    let attributes = &reply[start..start + len];
+    if attributes.chars().all(|c| c.is_ascii_digit() || c == ';') {
+        Some(attributes)
+    } else {
+        None
+    }
+}
+
+/// The two values of the window manipulation reply starting with `kind`, i.e.
+/// `\x1b[6;20;10t` for the cell size.
+fn window_reply(reply: &str, kind: &str) -> Option<(u32, u32)> {
+    let prefix = format!("\x1b[{};", kind);
+    let start = reply.find(&prefix)? + prefix.len();
+    let len = reply[start..].find('t')?;
+    let (first, second) = reply[start..start + len].split_once(';')?;
+    let (first, second) = (first.parse().ok()?, second.parse().ok()?);
+    if first == 0 || second == 0 {
+        return None;
+    }
+    Some((first, second))
+}
+
+fn da1_attributes(reply: &str) -> Vec<&str> {
+    da1_reply(reply).map_or(vec![], |attributes| attributes.split(';').collect())
+}
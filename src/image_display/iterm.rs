@@ -0,0 +1,166 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::ImageOutputFormat;
+use ratatui::buffer::Buffer;
+use std::{
+    env, fs,
+    io::{Cursor, Stdout, Write},
+    mem,
+};
+
+use super::{cover, erase_stale, ImageDisplay, Passthrough, Placement};
+use crate::animation;
+use crate::app::Zoom;
+use crate::format::Format;
+use crate::orientation::Orientation;
+
+// The formats terminals decode by themselves, others are sent as PNG
+const FILE_FORMATS: [Format; 6] = [
+    Format::Bmp,
+    Format::Gif,
+    Format::Jpeg,
+    Format::Png,
+    Format::Tiff,
+    Format::WebP,
+];
+
+/// Draws images with the inline images protocol (OSC 1337) of iTerm2, also
+/// understood by WezTerm, see https://iterm2.com/documentation-images.html
+///
+/// The file is sent as is, the terminal decodes it and fits it in the cells
+/// of the block.
+pub struct ItermDisplay<W: Write> {
+    writer: W,
+    passthrough: Passthrough,
+    pending: Vec<Placement>,
+    placed: Vec<Placement>,
+}
+
+impl<W: Write> ItermDisplay<W> {
+    pub fn new(writer: W, passthrough: Passthrough) -> Self {
+        ItermDisplay {
+            writer,
+            passthrough,
+            pending: vec![],
+            placed: vec![],
+        }
+    }
+
+    /// Whether the file is sent as is, for the terminal to decode.
+    fn sends_file(placement: &Placement) -> bool {
+        // the terminal would play animations by itself, out of step with the
+        // frames chosen here
+        placement.zoom == Zoom::Fit
+            && Orientation::read(&placement.image_path) == Orientation::Normal
+            && Format::sniff(&placement.image_path)
+                .is_some_and(|format| FILE_FORMATS.contains(&format))
+            && animation::playing_animation(&placement.image_path).is_none()
+            && !placement.thumbnail
+    }
+
+    /// Draw the image of `placement`, unless it's not decoded yet.
+    fn draw(&mut self, placement: &Placement) -> Result<bool> {
+        let block = placement.block;
+        let data = if Self::sends_file(placement) {
+            fs::read(&placement.image_path)?
+        } else {
+            // terminals don't agree on honoring the EXIF orientation, and
+            // can't crop, send the visible part already turned instead
+            let Some(image) = placement.load(self.cell_pixels())? else {
+                return Ok(false);
+            };
+            let mut data = vec![];
+            image.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)?;
+            data
+        };
+
+        let mut sequence = vec![];
+        write!(
+            sequence,
+            "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:",
+            data.len(),
+            block.width,
+            block.height
+        )?;
+        sequence.extend_from_slice(STANDARD.encode(data).as_bytes());
+        sequence.push(0x07);
+        self.passthrough
+            .write_at(&mut self.writer, block.x, block.y, &sequence)?;
+        Ok(true)
+    }
+}
+
+impl ItermDisplay<Stdout> {
+    pub fn is_supported() -> bool {
+        env::var("TERM_PROGRAM").is_ok_and(|program| program == "iTerm.app" || program == "WezTerm")
+    }
+}
+
+impl<W: Write> ImageDisplay for ItermDisplay<W> {
+    fn render_image(&mut self, placement: Placement, buf: &mut Buffer) -> Result<()> {
+        if placement.block.width > 0 && placement.block.height > 0 {
+            cover(buf, placement.block);
+            self.pending.push(placement);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let pending = mem::take(&mut self.pending);
+        if pending == self.placed {
+            return Ok(());
+        }
+
+        for placement in mem::take(&mut self.placed) {
+            erase_stale(&mut self.writer, placement.block, &pending)?;
+        }
+        // one broken image shouldn't hide the others, the first error is
+        // reported once they're all drawn
+        let mut drawn = Ok(());
+        for placement in pending {
+            match self.draw(&placement) {
+                Ok(true) => {}
+                // not decoded yet, it's drawn on a later frame
+                Ok(false) => continue,
+                Err(err) => drawn = drawn.and(Err(err)),
+            }
+            self.placed.push(placement);
+        }
+
+        self.writer.flush()?;
+        drawn
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.placed.clear();
+        self.passthrough.update_pane_offset();
+        Ok(())
+    }
+
+    fn decodes_image(&self, placement: &Placement) -> bool {
+        !Self::sends_file(placement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_display::passthrough::Multiplexer;
+    use crate::image_display::tests::placement;
+    use ratatui::layout::Rect;
+
+    #[test]
+    fn sends_the_file_as_is() {
+        let mut display = ItermDisplay::new(vec![], Passthrough::new(Multiplexer::None, (0, 0)));
+        let placement = placement("tests/fixtures/quadrants.png", Rect::new(2, 1, 8, 4));
+        display.draw(&placement).unwrap();
+
+        let mut expected =
+            b"\x1b[2;3H\x1b]1337;File=inline=1;size=151;width=8;height=4;preserveAspectRatio=1:"
+                .to_vec();
+        let file = include_bytes!("../../tests/fixtures/quadrants.png");
+        expected.extend_from_slice(STANDARD.encode(file).as_bytes());
+        expected.push(0x07);
+        assert_eq!(display.writer, expected);
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::RgbaImage;
+use ratatui::{buffer::Buffer, layout::Rect};
+use std::{
+    env,
+    io::{Stdout, Write},
+    mem,
+};
+
+use super::{ImageDisplay, Passthrough, Placement};
+
+// The kitty graphics protocol requires the payload to be sent in chunks of
+// at most 4096 bytes.
+const CHUNK_SIZE: usize = 4096;
+
+/// Draws images with the kitty graphics protocol, see
+/// https://sw.kovidgoyal.net/kitty/graphics-protocol/
+///
+/// Every image is transmitted once, with its own id, and deleted (freeing its
+/// data in the terminal) as soon as it isn't part of a frame anymore.
+pub struct KittyDisplay<W: Write> {
+    writer: W,
+    passthrough: Passthrough,
+    next_id: u32,
+    pending: Vec<Placement>,
+    placed: Vec<(Placement, u32)>,
+}
+
+impl<W: Write> KittyDisplay<W> {
+    pub fn new(writer: W, passthrough: Passthrough) -> Self {
+        KittyDisplay {
+            writer,
+            passthrough,
+            next_id: 1,
+            pending: vec![],
+            placed: vec![],
+        }
+    }
+
+    fn transmit(&mut self, id: u32, image: &RgbaImage) -> Result<()> {
+        let payload = STANDARD.encode(image.as_raw());
+        let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK_SIZE).collect();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = if i + 1 < chunks.len() { 1 } else { 0 };
+            let mut sequence = vec![];
+            if i == 0 {
+                write!(
+                    sequence,
+                    "\x1b_Ga=t,f=32,s={},v={},i={},q=2,m={};",
+                    image.width(),
+                    image.height(),
+                    id,
+                    more
+                )?;
+            } else {
+                write!(sequence, "\x1b_Gm={};", more)?;
+            }
+            sequence.extend_from_slice(chunk);
+            sequence.extend_from_slice(b"\x1b\\");
+            self.passthrough.write(&mut self.writer, &sequence)?;
+        }
+
+        Ok(())
+    }
+
+    fn place(&mut self, id: u32, block: Rect) -> Result<()> {
+        // C=1 keeps the cursor where it is, otherwise the terminal could
+        // scroll when the image touches the bottom of the screen.
+        let sequence = format!("\x1b_Ga=p,i={},q=2,C=1\x1b\\", id);
+        self.passthrough
+            .write_at(&mut self.writer, block.x, block.y, sequence.as_bytes())?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u32) -> Result<()> {
+        let sequence = format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id);
+        self.passthrough
+            .write(&mut self.writer, sequence.as_bytes())?;
+        Ok(())
+    }
+
+    /// Draw the image of `placement`, unless it's not decoded yet. Returns
+    /// the id it's placed with.
+    fn draw(&mut self, placement: &Placement) -> Result<Option<u32>> {
+        let Some(image) = placement.load(self.cell_pixels())? else {
+            return Ok(None);
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.transmit(id, &image.to_rgba8())?;
+        self.place(id, placement.block)?;
+        Ok(Some(id))
+    }
+}
+
+impl KittyDisplay<Stdout> {
+    pub fn is_supported() -> bool {
+        env::var("KITTY_WINDOW_ID").is_ok()
+            || env::var("TERM").is_ok_and(|term| term == "xterm-kitty")
+    }
+}
+
+impl<W: Write> ImageDisplay for KittyDisplay<W> {
+    fn render_image(&mut self, placement: Placement, _buf: &mut Buffer) -> Result<()> {
+        if placement.block.width > 0 && placement.block.height > 0 {
+            self.pending.push(placement);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let pending = mem::take(&mut self.pending);
+        let (kept, stale): (Vec<_>, Vec<_>) = mem::take(&mut self.placed)
+            .into_iter()
+            .partition(|(placement, _)| pending.contains(placement));
+        self.placed = kept;
+
+        for (_, id) in stale {
+            self.delete(id)?;
+        }
+
+        // one broken image shouldn't hide the others, the first error is
+        // reported once they're all drawn
+        let mut drawn = Ok(());
+        for placement in pending {
+            if self.placed.iter().any(|(p, _)| *p == placement) {
+                continue;
+            }
+            match self.draw(&placement) {
+                Ok(Some(id)) => self.placed.push((placement, id)),
+                // not decoded yet, it's drawn on a later frame
+                Ok(None) => {}
+                Err(err) => drawn = drawn.and(Err(err)),
+            }
+        }
+
+        self.writer.flush()?;
+        drawn
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        // kitty keeps the image data around, even when the screen is cleared
+        for (_, id) in mem::take(&mut self.placed) {
+            self.delete(id)?;
+        }
+        self.writer.flush()?;
+        self.passthrough.update_pane_offset();
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for KittyDisplay<W> {
+    fn drop(&mut self) {
+        for (_, id) in mem::take(&mut self.placed) {
+            let _ = self.delete(id);
+        }
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_display::passthrough::Multiplexer;
+
+    fn display() -> KittyDisplay<Vec<u8>> {
+        KittyDisplay::new(vec![], Passthrough::new(Multiplexer::None, (0, 0)))
+    }
+
+    #[test]
+    fn transmits_rgba_pixels() {
+        let mut display = display();
+        let image = RgbaImage::from_raw(1, 1, vec![1, 2, 3, 4]).unwrap();
+        display.transmit(7, &image).unwrap();
+        assert_eq!(
+            display.writer,
+            b"\x1b_Ga=t,f=32,s=1,v=1,i=7,q=2,m=0;AQIDBA==\x1b\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn transmits_in_chunks() {
+        let mut display = display();
+        // 4100 bytes of pixels take 5468 bytes of base64
+        let image = RgbaImage::from_raw(1025, 1, vec![0; 4100]).unwrap();
+        display.transmit(1, &image).unwrap();
+
+        let mut expected = b"\x1b_Ga=t,f=32,s=1025,v=1,i=1,q=2,m=1;".to_vec();
+        expected.extend_from_slice(&[b'A'; CHUNK_SIZE]);
+        expected.extend_from_slice(b"\x1b\\\x1b_Gm=0;");
+        // the last 2 bytes are padded
+        expected.extend_from_slice(&[b'A'; 5468 - CHUNK_SIZE - 1]);
+        expected.extend_from_slice(b"=\x1b\\");
+        assert_eq!(display.writer, expected);
+    }
+
+    #[test]
+    fn places_at_the_top_left_cell_of_the_block() {
+        let mut display = display();
+        display.place(7, Rect::new(2, 3, 10, 5)).unwrap();
+        assert_eq!(
+            display.writer,
+            b"\x1b[4;3H\x1b_Ga=p,i=7,q=2,C=1\x1b\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn deletes_by_id() {
+        let mut display = display();
+        display.delete(7).unwrap();
+        assert_eq!(display.writer, b"\x1b_Ga=d,d=I,i=7,q=2\x1b\\".to_vec());
+    }
+}
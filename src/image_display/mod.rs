@@ -0,0 +1,535 @@
+mod blocks;
+mod cache;
+mod detect;
+mod iterm;
+mod kitty;
+mod passthrough;
+mod sixel;
+mod w3m;
+
+use anyhow::{anyhow, Result};
+use image::{imageops::FilterType, io::Reader, DynamicImage};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+};
+use std::{
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+use termion::cursor::Goto;
+
+use crate::animation;
+use crate::app::Zoom;
+use crate::format::Format;
+use crate::heif;
+use crate::orientation::Orientation;
+use crate::raw;
+use crate::svg;
+use crate::thumbnails;
+
+pub use blocks::BlocksDisplay;
+pub use cache::{cache_failure, cache_image, is_cached};
+pub use detect::detect;
+pub use iterm::ItermDisplay;
+pub use kitty::KittyDisplay;
+pub use passthrough::Passthrough;
+pub use sixel::SixelDisplay;
+pub use w3m::W3mDisplay;
+
+// Used when the terminal doesn't report its size in pixels.
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    Auto,
+    Blocks,
+    Iterm,
+    Kitty,
+    None,
+    Sixel,
+    W3m,
+}
+
+impl FromStr for Renderer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Renderer::Auto),
+            "blocks" => Ok(Renderer::Blocks),
+            "iterm" => Ok(Renderer::Iterm),
+            "kitty" => Ok(Renderer::Kitty),
+            "none" => Ok(Renderer::None),
+            "sixel" => Ok(Renderer::Sixel),
+            "w3m" => Ok(Renderer::W3m),
+            _ => Err(anyhow!("unknown renderer `{}`", s)),
+        }
+    }
+}
+
+/// An image, the cells it should be drawn in, and how much of it is shown.
+/// Backends which only write to the terminal on `flush` compare them to tell
+/// what changed since the last frame.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Placement {
+    pub image_path: PathBuf,
+    pub block: Rect,
+    pub zoom: Zoom,
+    /// Frame of an animated image, 0 for still ones
+    pub animation_frame: usize,
+    /// Whether it's a small preview, which can come from the thumbnail cache
+    pub thumbnail: bool,
+}
+
+impl Placement {
+    /// What to decode for `block`, with cells of `cell_width` x `cell_height`
+    /// pixels.
+    pub fn key(&self, (cell_width, cell_height): (u32, u32)) -> ImageKey {
+        ImageKey {
+            image_path: self.image_path.clone(),
+            zoom: self.zoom,
+            animation_frame: self.animation_frame,
+            thumbnail: self.thumbnail,
+            max_width: self.block.width as u32 * cell_width,
+            max_height: self.block.height as u32 * cell_height,
+        }
+    }
+
+    /// Decode the image for `block`, unless it was already decoded, e.g. by
+    /// the prefetcher. Images needing a converter are left to the
+    /// prefetcher, `None` until it's done, see `take_awaited`.
+    fn load(&self, cell_pixels: (u32, u32)) -> Result<Option<DynamicImage>> {
+        let key = self.key(cell_pixels);
+        if let Some(image) = cache::get(&key) {
+            return Ok(Some(image));
+        }
+        if let Some(failure) = cache::failure(&key) {
+            return Err(anyhow!(failure));
+        }
+        if Format::sniff(&self.image_path).is_some_and(Format::needs_converter) {
+            if let Ok(mut awaited) = AWAITED.lock() {
+                if !awaited.contains(&key) {
+                    awaited.push(key);
+                }
+            }
+            return Ok(None);
+        }
+
+        let image = key.decode()?;
+        // the frames of the animation being played are kept by it already,
+        // caching each of them would push the prefetched images out
+        if animation::playing_animation(&self.image_path).is_none() {
+            cache_image(key, image.clone());
+        }
+        Ok(Some(image))
+    }
+}
+
+/// Images which were drawn before they were decoded, as converting them takes
+/// a while. They're blank until the prefetcher is done with them.
+static AWAITED: Mutex<Vec<ImageKey>> = Mutex::new(vec![]);
+
+/// The images `Placement::load` left blank since it was last called, for the
+/// prefetcher to decode first.
+pub fn take_awaited() -> Vec<ImageKey> {
+    AWAITED
+        .lock()
+        .map(|mut awaited| mem::take(&mut *awaited))
+        .unwrap_or_default()
+}
+
+/// An image decoded for a given zoom and size. Placements of the same size
+/// share it, wherever they are on the screen.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageKey {
+    image_path: PathBuf,
+    zoom: Zoom,
+    animation_frame: usize,
+    thumbnail: bool,
+    max_width: u32,
+    max_height: u32,
+}
+
+impl ImageKey {
+    pub fn decode(&self) -> Result<DynamicImage> {
+        if self.thumbnail {
+            let size = self.max_width.max(self.max_height);
+            if let Some(thumbnail) = thumbnails::load(&self.image_path, size)? {
+                let (width, height) = (thumbnail.width(), thumbnail.height());
+                let (width, height) = fit(width, height, self.max_width, self.max_height);
+                return Ok(thumbnail.resize_exact(width, height, FilterType::Triangle));
+            }
+        }
+
+        load_image(
+            &self.image_path,
+            self.zoom,
+            self.animation_frame,
+            self.max_width,
+            self.max_height,
+        )
+    }
+}
+
+pub trait ImageDisplay {
+    /// Draw (or queue for drawing) an image. Backends drawing with characters
+    /// write them to `buf`, the frame being rendered.
+    fn render_image(&mut self, placement: Placement, buf: &mut Buffer) -> Result<()>;
+
+    /// Called once per frame, after ratatui has written its buffer. Backends
+    /// which write escape sequences to the terminal do it here, so the text
+    /// drawn by ratatui doesn't end up on top of the image.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether images are turned according to their EXIF orientation.
+    fn auto_rotates(&self) -> bool {
+        true
+    }
+
+    /// Called when the screen was cleared, e.g. after a resize. The images on
+    /// it are gone, and all of them must be drawn again on the next frame.
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// How many image pixels fit in a cell, at 100% zoom.
+    fn cell_pixels(&self) -> (u32, u32) {
+        cell_size().unwrap_or(DEFAULT_CELL_SIZE)
+    }
+
+    /// The formats it can draw. The app decodes all of them, backends
+    /// handing files over to another program may not.
+    fn supported_formats(&self) -> &[Format] {
+        &Format::ALL
+    }
+
+    /// Whether the image of `placement` is decoded by the app, and not by the
+    /// terminal or an external program. Only then is decoding it ahead of time
+    /// any use.
+    fn decodes_image(&self, _placement: &Placement) -> bool {
+        true
+    }
+}
+
+pub fn new_image_display(renderer: Renderer) -> Result<Box<dyn ImageDisplay>> {
+    query_cell_size();
+
+    Ok(match renderer {
+        Renderer::Auto => new_image_display(detect())?,
+        Renderer::Blocks => Box::new(BlocksDisplay::new()),
+        Renderer::Iterm => Box::new(ItermDisplay::new(io::stdout(), Passthrough::detect())),
+        Renderer::Kitty => Box::new(KittyDisplay::new(io::stdout(), Passthrough::detect())),
+        Renderer::None => Box::new(NoDisplay),
+        Renderer::Sixel => Box::new(SixelDisplay::new(io::stdout(), Passthrough::detect())),
+        Renderer::W3m => Box::new(W3mDisplay::new()?),
+    })
+}
+
+/// Doesn't draw images at all, for sorting by file name only.
+pub struct NoDisplay;
+
+impl ImageDisplay for NoDisplay {
+    fn render_image(&mut self, _placement: Placement, _buf: &mut Buffer) -> Result<()> {
+        Ok(())
+    }
+
+    fn decodes_image(&self, _placement: &Placement) -> bool {
+        false
+    }
+}
+
+/// Mark the cells an image is drawn over in the frame being rendered. They
+/// look blank, but differ from plain blank cells, so once the image is gone
+/// ratatui draws whatever takes their place over its pixels.
+fn cover(buf: &mut Buffer, block: Rect) {
+    if !block.intersects(buf.area) {
+        return;
+    }
+    let block = block.intersection(buf.area);
+    for y in block.top()..block.bottom() {
+        for x in block.left()..block.right() {
+            buf.get_mut(x, y)
+                .set_symbol(" ")
+                .set_style(Style::default().add_modifier(Modifier::HIDDEN));
+        }
+    }
+}
+
+/// Overwrite `block` with blanks, removing whatever image was drawn there.
+fn erase<W: Write>(writer: &mut W, block: Rect) -> io::Result<()> {
+    let blank = " ".repeat(block.width as usize);
+    for row in block.top()..block.bottom() {
+        write!(writer, "{}{}", Goto(block.x + 1, row + 1), blank)?;
+    }
+    Ok(())
+}
+
+/// Erase the parts of the `stale` image which are under one of the `placed`
+/// ones, before they're drawn. ratatui already drew over the rest, see
+/// `cover`.
+fn erase_stale<W: Write>(writer: &mut W, stale: Rect, placed: &[Placement]) -> io::Result<()> {
+    for placement in placed {
+        if stale.intersects(placement.block) {
+            erase(writer, stale.intersection(placement.block))?;
+        }
+    }
+    Ok(())
+}
+
+/// Size of a terminal cell in pixels. The window size from the kernel
+/// (TIOCGWINSZ) is preferred, as it follows resizes, but it's often missing
+/// its pixel fields under Wayland or tmux. The terminal's answer to
+/// `query_cell_size` is used then.
+pub fn cell_size() -> Option<(u32, u32)> {
+    window_cell_size().or_else(|| QUERIED_CELL_SIZE.get().copied().flatten())
+}
+
+/// Ask the terminal for its cell size, to be used by `cell_size`. Like other
+/// queries, it must be done before the events listener reads stdin.
+pub fn query_cell_size() {
+    QUERIED_CELL_SIZE.get_or_init(detect::query_cell_size);
+}
+
+static QUERIED_CELL_SIZE: OnceLock<Option<(u32, u32)>> = OnceLock::new();
+
+fn window_cell_size() -> Option<(u32, u32)> {
+    let (columns, rows) = termion::terminal_size().ok()?;
+    let (width, height) = termion::terminal_size_pixels().ok()?;
+    if columns == 0 || rows == 0 || width == 0 || height == 0 {
+        return None;
+    }
+
+    Some((width as u32 / columns as u32, height as u32 / rows as u32))
+}
+
+/// Scale `width` x `height` down, keeping the aspect ratio, so that it fits in
+/// `max_width` x `max_height`. Sizes which already fit are left as they are.
+pub fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let (mut width, mut height) = (width as u64, height as u64);
+    let (max_width, max_height) = (max_width as u64, max_height as u64);
+    if width > max_width {
+        height = (max_width * height / width).max(1);
+        width = max_width;
+    }
+    if height > max_height {
+        width = (max_height * width / height).max(1);
+        height = max_height;
+    }
+    (width as u32, height as u32)
+}
+
+/// The part of a `width` x `height` image shown with `zoom` in `max_width` x
+/// `max_height` pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    /// Left, top, width and height of the visible part, in image pixels
+    pub crop: (u32, u32, u32, u32),
+    /// Size the visible part is scaled to
+    pub size: (u32, u32),
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32, max_width: u32, max_height: u32, zoom: Zoom) -> Self {
+        let (percent, (center_x, center_y)) = match zoom {
+            Zoom::Fit => {
+                return Viewport {
+                    crop: (0, 0, width, height),
+                    size: fit(width, height, max_width, max_height),
+                }
+            }
+            Zoom::Scale { percent, center } => (percent as u64, center),
+        };
+
+        let crop = |length: u32, max_length: u32, center: u32| {
+            let length = length.max(1) as u64;
+            let visible = (max_length as u64 * 100 / percent).clamp(1, length);
+            let start = (length * center as u64 / 1000)
+                .saturating_sub(visible / 2)
+                .min(length - visible);
+            let scaled = (visible * percent / 100).clamp(1, max_length.max(1) as u64);
+            (start as u32, visible as u32, scaled as u32)
+        };
+        let (x, crop_width, scaled_width) = crop(width, max_width, center_x);
+        let (y, crop_height, scaled_height) = crop(height, max_height, center_y);
+
+        Viewport {
+            crop: (x, y, crop_width, crop_height),
+            size: (scaled_width, scaled_height),
+        }
+    }
+}
+
+/// Decode an image as it's stored, whichever its format.
+pub fn decode_image(image_path: &Path) -> Result<DynamicImage> {
+    match Format::sniff(image_path) {
+        Some(format) if format.needs_converter() => heif::decode(image_path, format),
+        Some(Format::Raw) => raw::decode_preview(image_path),
+        Some(Format::Svg) => svg::decode(image_path),
+        _ => Ok(Reader::open(image_path)?.with_guessed_format()?.decode()?),
+    }
+}
+
+/// Decode an image, turned the right way up according to its EXIF
+/// orientation, and scaled down (keeping the aspect ratio) if it doesn't fit
+/// in `max_width` x `max_height` pixels. When zoomed in, only the visible part
+/// is returned. `animation_frame` picks the frame of an animated image.
+pub fn load_image(
+    image_path: &Path,
+    zoom: Zoom,
+    animation_frame: usize,
+    max_width: u32,
+    max_height: u32,
+) -> Result<DynamicImage> {
+    // vectors are drawn right at the size they're shown, to stay sharp
+    if Format::sniff(image_path) == Some(Format::Svg) {
+        return svg::rasterize(image_path, zoom, max_width, max_height);
+    }
+
+    let image = match animation::playing_animation(image_path) {
+        Some(animation) => DynamicImage::ImageRgba8(animation.frame(animation_frame).clone()),
+        None => decode_image(image_path)?,
+    };
+    let orientation = Orientation::read(image_path);
+
+    if zoom == Zoom::Fit {
+        // fit the image as it's shown, but scale the pixels as they're stored
+        let (width, height) = orientation.dimensions((image.width(), image.height()));
+        let (width, height) = orientation.dimensions(fit(width, height, max_width, max_height));
+        let image = if (width, height) != (image.width(), image.height()) {
+            image.resize_exact(width, height, FilterType::Triangle)
+        } else {
+            image
+        };
+        return Ok(orientation.apply(image));
+    }
+
+    let image = orientation.apply(image);
+    let viewport = Viewport::new(image.width(), image.height(), max_width, max_height, zoom);
+    let (x, y, width, height) = viewport.crop;
+    let (scaled_width, scaled_height) = viewport.size;
+    // nearest neighbor, so pixels stay sharp when zooming past 100%
+    Ok(image.crop_imm(x, y, width, height).resize_exact(
+        scaled_width,
+        scaled_height,
+        FilterType::Nearest,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    /// A still image fit in `block`, for the backends' tests.
+    pub fn placement(image_path: &str, block: Rect) -> Placement {
+        Placement {
+            image_path: PathBuf::from(image_path),
+            block,
+            zoom: Zoom::Fit,
+            animation_frame: 0,
+            thumbnail: false,
+        }
+    }
+
+    #[test]
+    fn leaves_converting_to_the_prefetcher() {
+        let image_path = env::temp_dir().join(format!(
+            "{}-{}-awaited.heic",
+            env!("CARGO_PKG_NAME"),
+            process::id()
+        ));
+        fs::write(
+            &image_path,
+            [&24u32.to_be_bytes(), &b"ftypheic\0\0\0\0mif1heic"[..]].concat(),
+        )
+        .unwrap();
+        let placement = placement(image_path.to_str().unwrap(), Rect::new(0, 0, 8, 4));
+        let key = placement.key((1, 2));
+
+        assert!(placement.load((1, 2)).unwrap().is_none());
+        assert!(take_awaited().contains(&key));
+
+        cache_failure(key, anyhow!("no converter"));
+        assert_eq!(
+            placement.load((1, 2)).unwrap_err().to_string(),
+            "no converter"
+        );
+        fs::remove_file(image_path).unwrap();
+    }
+
+    #[test]
+    fn covers_the_cells_under_images() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 3));
+        let block = Rect::new(1, 1, 2, 1);
+        cover(&mut buf, block);
+
+        for y in 0..3 {
+            for x in 0..4 {
+                let hidden = buf.get(x, y).modifier.contains(Modifier::HIDDEN);
+                assert_eq!(hidden, block.intersects(Rect::new(x, y, 1, 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn covers_only_the_visible_part() {
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 3));
+        cover(&mut buf, Rect::new(2, 2, 10, 10));
+        cover(&mut buf, Rect::new(5, 5, 1, 1));
+
+        let hidden = buf
+            .content
+            .iter()
+            .filter(|cell| cell.modifier.contains(Modifier::HIDDEN))
+            .count();
+        assert_eq!(hidden, 2);
+    }
+
+    #[test]
+    fn leaves_stale_images_to_ratatui() {
+        let mut writer = vec![];
+        let placed = [placement("image.png", Rect::new(20, 0, 10, 5))];
+        erase_stale(&mut writer, Rect::new(0, 0, 10, 5), &placed).unwrap();
+        assert!(writer.is_empty());
+    }
+
+    #[test]
+    fn erases_under_new_images() {
+        let mut writer = vec![];
+        let placed = [placement("image.png", Rect::new(8, 3, 10, 5))];
+        erase_stale(&mut writer, Rect::new(0, 0, 10, 5), &placed).unwrap();
+        assert_eq!(writer, b"\x1b[4;9H  \x1b[5;9H  ".to_vec());
+    }
+
+    #[test]
+    fn letterboxes_wide_images() {
+        assert_eq!(fit(400, 100, 200, 200), (200, 50));
+    }
+
+    #[test]
+    fn pillarboxes_tall_images() {
+        assert_eq!(fit(100, 400, 200, 200), (50, 200));
+    }
+
+    #[test]
+    fn fits_to_the_tighter_side() {
+        assert_eq!(fit(300, 200, 150, 50), (75, 50));
+    }
+
+    #[test]
+    fn doesnt_upscale() {
+        assert_eq!(fit(10, 20, 200, 200), (10, 20));
+    }
+
+    #[test]
+    fn keeps_at_least_a_pixel() {
+        assert_eq!(fit(1000, 1, 100, 100), (100, 1));
+    }
+}
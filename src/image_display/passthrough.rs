@@ -0,0 +1,214 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::Command,
+};
+use termion::cursor::Goto;
+
+// GNU screen drops DCS strings longer than this
+const SCREEN_CHUNK_SIZE: usize = 768;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    None,
+    Tmux,
+    Screen,
+}
+
+/// Gets escape sequences for images through tmux or GNU screen, which would
+/// otherwise swallow them, to the terminal they run in.
+///
+/// The multiplexer doesn't know where the terminal's cursor is when the
+/// sequence arrives, so it's moved as part of it, to the cell relative to
+/// the whole window, not to the pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passthrough {
+    multiplexer: Multiplexer,
+    // column and row of the top left cell of the pane, in the window
+    pane_offset: (u16, u16),
+}
+
+impl Passthrough {
+    pub fn new(multiplexer: Multiplexer, pane_offset: (u16, u16)) -> Self {
+        Passthrough {
+            multiplexer,
+            pane_offset,
+        }
+    }
+
+    /// Tell from the environment whether we're running in tmux or screen.
+    pub fn detect() -> Self {
+        let multiplexer = if env::var_os("TMUX").is_some() {
+            Multiplexer::Tmux
+        } else if env::var_os("STY").is_some() {
+            Multiplexer::Screen
+        } else {
+            Multiplexer::None
+        };
+
+        let mut passthrough = Passthrough::new(multiplexer, (0, 0));
+        passthrough.update_pane_offset();
+        passthrough
+    }
+
+    /// Panes move around when the window is split or resized. Only tmux can
+    /// tell where they are, screen regions are assumed to start at the top
+    /// left.
+    pub fn update_pane_offset(&mut self) {
+        if self.multiplexer == Multiplexer::Tmux {
+            self.pane_offset = tmux_pane_offset().unwrap_or((0, 0));
+        }
+    }
+
+    /// Write `sequence` so that it reaches the terminal.
+    pub fn write<W: Write>(&self, writer: &mut W, sequence: &[u8]) -> io::Result<()> {
+        match self.multiplexer {
+            Multiplexer::None => writer.write_all(sequence),
+            Multiplexer::Tmux => writer.write_all(&wrap_tmux(sequence)),
+            Multiplexer::Screen => writer.write_all(&wrap_screen(sequence)),
+        }
+    }
+
+    /// Write `sequence` so that it reaches the terminal, with its cursor at
+    /// column `x` and row `y` of the pane, counting from 0.
+    pub fn write_at<W: Write>(
+        &self,
+        writer: &mut W,
+        x: u16,
+        y: u16,
+        sequence: &[u8],
+    ) -> io::Result<()> {
+        // the multiplexer's own cursor goes there too, so it redraws the
+        // right cells over the image later on
+        write!(writer, "{}", Goto(x + 1, y + 1))?;
+        if self.multiplexer == Multiplexer::None {
+            return writer.write_all(sequence);
+        }
+
+        let (column, row) = self.pane_offset;
+        let mut positioned = Goto(column + x + 1, row + y + 1).to_string().into_bytes();
+        positioned.extend_from_slice(sequence);
+        self.write(writer, &positioned)
+    }
+}
+
+/// Wrap `sequence` in a tmux passthrough DCS, where each escape is doubled.
+/// tmux only passes it on with `set -g allow-passthrough on`.
+pub fn wrap_tmux(sequence: &[u8]) -> Vec<u8> {
+    let mut wrapped = b"\x1bPtmux;".to_vec();
+    for &byte in sequence {
+        if byte == 0x1b {
+            wrapped.push(0x1b);
+        }
+        wrapped.push(byte);
+    }
+    wrapped.extend_from_slice(b"\x1b\\");
+    wrapped
+}
+
+/// Wrap `sequence` in as many DCS strings as GNU screen needs to pass it on.
+///
+/// Screen has no way of escaping the ST (`ESC \`) which ends the string,
+/// so every DCS ends right after an escape: screen passes the lone escape
+/// on, and the byte after it starts the next DCS.
+pub fn wrap_screen(sequence: &[u8]) -> Vec<u8> {
+    let mut wrapped = vec![];
+    let mut rest = sequence;
+    while !rest.is_empty() {
+        let mut length = rest.len().min(SCREEN_CHUNK_SIZE);
+        if let Some(escape) = rest[..length].iter().position(|&byte| byte == 0x1b) {
+            length = escape + 1;
+        }
+
+        wrapped.extend_from_slice(b"\x1bP");
+        wrapped.extend_from_slice(&rest[..length]);
+        wrapped.extend_from_slice(b"\x1b\\");
+        rest = &rest[length..];
+    }
+    wrapped
+}
+
+fn tmux_pane_offset() -> Option<(u16, u16)> {
+    let mut command = Command::new("tmux");
+    command.args(["display-message", "-p"]);
+    if let Ok(pane) = env::var("TMUX_PANE") {
+        command.args(["-t", &pane]);
+    }
+    let output = command.arg("#{pane_left} #{pane_top}").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let (left, top) = output.trim().split_once(' ')?;
+    Some((left.parse().ok()?, top.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_doubles_escapes() {
+        assert_eq!(
+            wrap_tmux(b"\x1b_Ga=d,d=I,i=1,q=2\x1b\\"),
+            b"\x1bPtmux;\x1b\x1b_Ga=d,d=I,i=1,q=2\x1b\x1b\\\x1b\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn screen_ends_strings_after_escapes() {
+        assert_eq!(
+            wrap_screen(b"\x1bPq#0!4~\x1b\\"),
+            b"\x1bP\x1b\x1b\\\x1bPPq#0!4~\x1b\x1b\\\x1bP\\\x1b\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn screen_splits_long_sequences() {
+        let sequence = vec![b'~'; SCREEN_CHUNK_SIZE * 2 + 10];
+        let wrapped = wrap_screen(&sequence);
+
+        let mut expected = vec![];
+        for length in [SCREEN_CHUNK_SIZE, SCREEN_CHUNK_SIZE, 10] {
+            expected.extend_from_slice(b"\x1bP");
+            expected.extend(std::iter::repeat_n(b'~', length));
+            expected.extend_from_slice(b"\x1b\\");
+        }
+        assert_eq!(wrapped, expected);
+    }
+
+    #[test]
+    fn no_multiplexer_writes_as_is() {
+        let passthrough = Passthrough::new(Multiplexer::None, (0, 0));
+        let mut written = vec![];
+        passthrough
+            .write_at(&mut written, 2, 3, b"\x1b]1337;File=:\x07")
+            .unwrap();
+        assert_eq!(written, b"\x1b[4;3H\x1b]1337;File=:\x07".to_vec());
+    }
+
+    #[test]
+    fn tmux_moves_the_cursor_by_the_pane_offset() {
+        let passthrough = Passthrough::new(Multiplexer::Tmux, (81, 10));
+        let mut written = vec![];
+        passthrough
+            .write_at(&mut written, 2, 3, b"\x1b_Ga=p,i=1\x1b\\")
+            .unwrap();
+        assert_eq!(
+            written,
+            b"\x1b[4;3H\x1bPtmux;\x1b\x1b[14;84H\x1b\x1b_Ga=p,i=1\x1b\x1b\\\x1b\\".to_vec()
+        );
+    }
+
+    #[test]
+    fn screen_moves_the_cursor_inside_the_strings() {
+        let passthrough = Passthrough::new(Multiplexer::Screen, (0, 0));
+        let mut written = vec![];
+        passthrough.write_at(&mut written, 0, 0, b"\x07").unwrap();
+        assert_eq!(
+            written,
+            b"\x1b[1;1H\x1bP\x1b\x1b\\\x1bP[1;1H\x07\x1b\\".to_vec()
+        );
+    }
+}
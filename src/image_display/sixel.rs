@@ -0,0 +1,217 @@
+use anyhow::Result;
+use color_quant::NeuQuant;
+use image::RgbaImage;
+use ratatui::buffer::Buffer;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    mem,
+};
+
+use super::{cover, erase_stale, ImageDisplay, Passthrough, Placement};
+
+const PALETTE_SIZE: usize = 256;
+
+// NeuQuant sampling factor, 1 is the slowest and best quality, 30 the
+// fastest.
+const QUANTIZER_SAMPLE: i32 = 10;
+
+/// Encode `image` as a Sixel sequence, quantized to a palette of at most 256
+/// colors. Mostly transparent pixels are left untouched.
+pub fn encode<W: Write>(image: &RgbaImage, writer: &mut W) -> io::Result<()> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let quantizer = NeuQuant::new(QUANTIZER_SAMPLE, PALETTE_SIZE, image.as_raw());
+    let indices: Vec<Option<u8>> = image
+        .pixels()
+        .map(|pixel| {
+            if pixel[3] < 128 {
+                None
+            } else {
+                Some(quantizer.index_of(&pixel.0) as u8)
+            }
+        })
+        .collect();
+
+    // P2=1: pixels which are not painted keep the background color
+    write!(writer, "\x1bP0;1;0q\"1;1;{};{}", width, height)?;
+    for (i, color) in quantizer.color_map_rgb().chunks(3).enumerate() {
+        write!(
+            writer,
+            "#{};2;{};{};{}",
+            i,
+            color[0] as u32 * 100 / 255,
+            color[1] as u32 * 100 / 255,
+            color[2] as u32 * 100 / 255
+        )?;
+    }
+
+    for band in (0..height).step_by(6) {
+        // sixels of every color used in this band, one per column
+        let mut sixels: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+        for row in band..(band + 6).min(height) {
+            for column in 0..width {
+                if let Some(color) = indices[row * width + column] {
+                    let bits = sixels.entry(color).or_insert_with(|| vec![0; width]);
+                    bits[column] |= 1 << (row - band);
+                }
+            }
+        }
+
+        for (i, (color, bits)) in sixels.iter().enumerate() {
+            if i > 0 {
+                // carriage return, to paint the next color over the same band
+                writer.write_all(b"$")?;
+            }
+            write!(writer, "#{}", color)?;
+            write_runs(writer, bits)?;
+        }
+        writer.write_all(b"-")?;
+    }
+
+    writer.write_all(b"\x1b\\")
+}
+
+fn write_runs<W: Write>(writer: &mut W, bits: &[u8]) -> io::Result<()> {
+    let mut column = 0;
+    while column < bits.len() {
+        let run = bits[column..]
+            .iter()
+            .take_while(|&&b| b == bits[column])
+            .count();
+        let sixel = (bits[column] + 63) as char;
+        if run > 3 {
+            write!(writer, "!{}{}", run, sixel)?;
+        } else {
+            for _ in 0..run {
+                write!(writer, "{}", sixel)?;
+            }
+        }
+        column += run;
+    }
+    Ok(())
+}
+
+/// Draws images as Sixel graphics, for terminals like xterm, foot or mlterm.
+///
+/// Unlike kitty images, sixels are just pixels on the screen, so a stale image
+/// is removed by drawing over it, see `cover`.
+pub struct SixelDisplay<W: Write> {
+    writer: W,
+    passthrough: Passthrough,
+    pending: Vec<Placement>,
+    placed: Vec<Placement>,
+}
+
+impl<W: Write> SixelDisplay<W> {
+    pub fn new(writer: W, passthrough: Passthrough) -> Self {
+        SixelDisplay {
+            writer,
+            passthrough,
+            pending: vec![],
+            placed: vec![],
+        }
+    }
+
+    /// Draw the image of `placement`, unless it's not decoded yet.
+    fn draw(&mut self, placement: &Placement) -> Result<bool> {
+        let Some(image) = placement.load(self.cell_pixels())? else {
+            return Ok(false);
+        };
+        let image = image.to_rgba8();
+
+        let mut sequence = vec![];
+        encode(&image, &mut sequence)?;
+        let block = placement.block;
+        self.passthrough
+            .write_at(&mut self.writer, block.x, block.y, &sequence)?;
+        Ok(true)
+    }
+}
+
+impl<W: Write> ImageDisplay for SixelDisplay<W> {
+    fn render_image(&mut self, placement: Placement, buf: &mut Buffer) -> Result<()> {
+        if placement.block.width > 0 && placement.block.height > 0 {
+            cover(buf, placement.block);
+            self.pending.push(placement);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let pending = mem::take(&mut self.pending);
+        if pending == self.placed {
+            return Ok(());
+        }
+
+        for placement in mem::take(&mut self.placed) {
+            erase_stale(&mut self.writer, placement.block, &pending)?;
+        }
+        // one broken image shouldn't hide the others, the first error is
+        // reported once they're all drawn
+        let mut drawn = Ok(());
+        for placement in pending {
+            match self.draw(&placement) {
+                Ok(true) => {}
+                // not decoded yet, it's drawn on a later frame
+                Ok(false) => continue,
+                Err(err) => drawn = drawn.and(Err(err)),
+            }
+            self.placed.push(placement);
+        }
+
+        self.writer.flush()?;
+        drawn
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.placed.clear();
+        self.passthrough.update_pane_offset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_display::passthrough::Multiplexer;
+    use crate::image_display::tests::placement;
+    use ratatui::layout::Rect;
+
+    // the sample images and the sequences they're expected to turn into are
+    // in tests/fixtures
+    fn assert_encodes(image: &[u8], sequence: &[u8]) {
+        let image = image::load_from_memory(image).unwrap().to_rgba8();
+        let mut encoded = vec![];
+        encode(&image, &mut encoded).unwrap();
+        assert!(encoded == sequence, "{}", String::from_utf8_lossy(&encoded));
+    }
+
+    #[test]
+    fn encodes_png() {
+        assert_encodes(
+            include_bytes!("../../tests/fixtures/quadrants.png"),
+            include_bytes!("../../tests/fixtures/quadrants.six"),
+        );
+    }
+
+    #[test]
+    fn encodes_jpeg() {
+        assert_encodes(
+            include_bytes!("../../tests/fixtures/gradient.jpg"),
+            include_bytes!("../../tests/fixtures/gradient.six"),
+        );
+    }
+
+    #[test]
+    fn draws_the_images_after_a_broken_one() {
+        let mut display = SixelDisplay::new(vec![], Passthrough::new(Multiplexer::None, (0, 0)));
+        display.pending = vec![
+            placement("tests/fixtures/missing.png", Rect::new(0, 0, 8, 4)),
+            placement("tests/fixtures/quadrants.png", Rect::new(8, 0, 8, 4)),
+        ];
+        assert!(display.flush().is_err());
+
+        assert!(display.writer.starts_with(b"\x1b[1;9H\x1bP"));
+        assert_eq!(display.placed.len(), 2);
+    }
+}
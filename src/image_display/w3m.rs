@@ -0,0 +1,286 @@
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use ratatui::{buffer::Buffer, layout::Rect};
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+use subprocess::{Popen, PopenConfig, Redirection};
+
+use super::{cell_size, ImageDisplay, Placement, Viewport};
+use crate::dimensions::DimensionsCache;
+use crate::format::Format;
+
+// How long w3mimgdisplay may take to answer, drawing a large image included,
+// before it's deemed hung.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A w3mimgdisplay process, kept alive between frames, which we talk to
+/// through its stdin and stdout. Its stdout is read on a thread of its own, so
+/// waiting for an answer can time out.
+struct Coprocess {
+    process: Popen,
+    stdin: File,
+    lines: Receiver<io::Result<String>>,
+}
+
+impl Coprocess {
+    fn spawn(path: &str) -> Result<Self> {
+        let mut process = Popen::create(
+            &[path],
+            PopenConfig {
+                stdin: Redirection::Pipe,
+                stdout: Redirection::Pipe,
+                ..PopenConfig::default()
+            },
+        )?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+
+        // ends with the process, when its stdout is closed
+        let (sender, lines) = unbounded();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Coprocess {
+            process,
+            stdin,
+            lines,
+        })
+    }
+
+    /// Send `commands`, followed by `4;`, which w3mimgdisplay answers with an
+    /// empty line, and return the lines written before it.
+    fn send(&mut self, commands: &str) -> io::Result<Vec<String>> {
+        writeln!(self.stdin, "{}4;", commands)?;
+        self.stdin.flush()?;
+
+        let mut lines = vec![];
+        loop {
+            let line = match self.lines.recv_timeout(RESPONSE_TIMEOUT) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::ErrorKind::UnexpectedEof.into())
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                return Ok(lines);
+            }
+            lines.push(line.to_string());
+        }
+    }
+}
+
+impl Drop for Coprocess {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+    }
+}
+
+pub struct W3mDisplay {
+    path: String,
+    coprocess: Option<Coprocess>,
+    // font dimensions for the last terminal size
+    font_dimensions: Option<(Rect, (u32, u32))>,
+    dimensions: DimensionsCache,
+}
+
+impl W3mDisplay {
+    pub fn new() -> Result<Self> {
+        match W3mDisplay::find() {
+            Some(path) => Ok(W3mDisplay {
+                path,
+                coprocess: None,
+                font_dimensions: None,
+                dimensions: DimensionsCache::default(),
+            }),
+            None => Err(anyhow!("w3mimgdisplay is not available!")),
+        }
+    }
+
+    /// Path to the w3mimgdisplay binary, `W3MIMGDISPLAY_PATH` takes precedence
+    /// over the usual install locations.
+    pub fn find() -> Option<String> {
+        let mut paths = vec![
+            "/usr/lib/w3m/w3mimgdisplay",
+            "/usr/libexec/w3m/w3mimgdisplay",
+            "/usr/lib64/w3m/w3mimgdisplay",
+            "/usr/libexec64/w3m/w3mimgdisplay",
+            "/usr/local/libexec/w3m/w3mimgdisplay",
+        ];
+
+        let env_path = env::var("W3MIMGDISPLAY_PATH").unwrap_or_else(|_| "".to_string());
+        if !env_path.is_empty() {
+            paths.insert(0, &env_path);
+        }
+
+        paths
+            .into_iter()
+            .find(|path| Path::new(path).exists())
+            .map(|path| path.to_string())
+    }
+
+    /// Send `commands` to the coprocess, spawning it if needed. When it died or
+    /// hung, it is respawned for the next frame and an error is returned.
+    fn send(&mut self, commands: &str) -> Result<Vec<String>> {
+        let coprocess = match &mut self.coprocess {
+            Some(coprocess) => coprocess,
+            None => self.coprocess.insert(Coprocess::spawn(&self.path)?),
+        };
+
+        coprocess.send(commands).map_err(|err| {
+            // the hung process is killed when it's dropped
+            self.coprocess = None;
+            match Coprocess::spawn(&self.path) {
+                Ok(coprocess) => {
+                    self.coprocess = Some(coprocess);
+                    anyhow!("w3mimgdisplay stopped responding ({}), restarted it", err)
+                }
+                Err(spawn_err) => anyhow!(
+                    "w3mimgdisplay stopped responding ({}), and can't be restarted: {}",
+                    err,
+                    spawn_err
+                ),
+            }
+        })
+    }
+
+    fn w3m_input(&mut self, placement: &Placement, terminal: Rect) -> Result<String> {
+        let (fontw, fonth) = self.font_dimensions(terminal)?;
+        let block = placement.block;
+
+        let start_x = (block.x as u32 + 1) * fontw;
+        let start_y = (block.y as u32 + 1) * fonth;
+
+        let max_width = (block.width as u32 - 1) * fontw;
+        let max_height = (block.height as u32 - 1) * fonth;
+
+        let (width, height) = self.image_dimensions(&placement.image_path)?;
+        let viewport = Viewport::new(width, height, max_width, max_height, placement.zoom);
+        let (crop_x, crop_y, crop_width, crop_height) = viewport.crop;
+        let (width, height) = viewport.size;
+
+        let input = format!(
+            "0;1;{};{};{};{};{};{};{};{};{}\n3;\n",
+            start_x,
+            start_y,
+            width,
+            height,
+            crop_x,
+            crop_y,
+            crop_width,
+            crop_height,
+            placement.image_path.display()
+        );
+
+        Ok(input)
+    }
+
+    fn image_dimensions(&mut self, image_path: &Path) -> Result<(u32, u32)> {
+        // w3mimgdisplay is only asked for formats we can't read the headers of
+        if let Ok(dimensions) = self.dimensions.get(image_path) {
+            return Ok(dimensions);
+        }
+
+        let outputs = self.send(&format!("5;{}\n", image_path.display()))?;
+        let outputs = outputs
+            .first()
+            .map_or(vec![], |output| output.split(' ').collect::<Vec<&str>>());
+        if outputs.len() < 2 {
+            return Err(anyhow!(
+                "w3mimagedisplay wrong output (image dimensions) for input file {}",
+                image_path.display()
+            ));
+        }
+
+        let width = outputs[0].parse::<u32>()?;
+        let height = outputs[1].parse::<u32>()?;
+
+        Ok((width, height))
+    }
+
+    fn font_dimensions(&mut self, terminal: Rect) -> Result<(u32, u32)> {
+        if let Some(dimensions) = cell_size() {
+            return Ok(dimensions);
+        }
+        // otherwise, w3mimgdisplay reports the size of the window
+        if let Some((size, dimensions)) = self.font_dimensions {
+            if size == terminal {
+                return Ok(dimensions);
+            }
+        }
+
+        let path = self.path.clone();
+        let mut process = Popen::create(
+            &[path, "-test".to_string()],
+            PopenConfig {
+                stdout: Redirection::Pipe,
+                ..PopenConfig::default()
+            },
+        )?;
+
+        let (out, err) = process.communicate(None)?;
+        process.kill()?;
+        if err.is_some() || out.is_none() {
+            return Err(anyhow!("w3mimagedisplay failed -test"));
+        }
+
+        let outputs = out.unwrap();
+        let outputs = outputs.trim().split(' ').collect::<Vec<&str>>();
+
+        if outputs.len() < 2 {
+            return Err(anyhow!("w3mimagedisplay wrong output (font dimensions)"));
+        }
+
+        let xwidth = outputs[0].parse::<u32>()? + 2;
+        let xheight = outputs[1].parse::<u32>()? + 2;
+
+        let dimensions = (
+            xwidth / terminal.width as u32,
+            xheight / terminal.height as u32,
+        );
+        self.font_dimensions = Some((terminal, dimensions));
+        Ok(dimensions)
+    }
+}
+
+impl ImageDisplay for W3mDisplay {
+    fn render_image(&mut self, placement: Placement, buf: &mut Buffer) -> Result<()> {
+        if placement.block.width > 0 && placement.block.height > 0 {
+            let input = self.w3m_input(&placement, *buf.area())?;
+            self.send(&input)?;
+        }
+        Ok(())
+    }
+
+    // w3mimgdisplay reads the file itself, and knows nothing about EXIF
+    fn auto_rotates(&self) -> bool {
+        false
+    }
+
+    // what imlib2 and gdk-pixbuf, which w3mimgdisplay is built with, agree on
+    fn supported_formats(&self) -> &[Format] {
+        &[
+            Format::Bmp,
+            Format::Gif,
+            Format::Jpeg,
+            Format::Png,
+            Format::Tiff,
+        ]
+    }
+
+    fn decodes_image(&self, _placement: &Placement) -> bool {
+        false
+    }
+}
@@ -5,12 +5,25 @@ use crate::app::{Action, App};
 pub fn handle_key_main(key: Key, app: &mut App) {
     match key {
         Key::Backspace => {
+            if !app.select_focused() {
+                return;
+            }
             if let Some(i) = app.current_image() {
                 app.push_action(Action::Delete(i));
             }
         }
         Key::Ctrl(key) => handle_app_key(key, app),
         Key::Char(key) => handle_mapping_key(key, app),
+        Key::PageUp => app.zoom_in(),
+        Key::PageDown => app.zoom_out(),
+        Key::Home => app.zoom_fit(),
+        Key::End => app.zoom_actual_size(),
+        Key::Left => app.pan(-1, 0),
+        Key::Right => app.pan(1, 0),
+        Key::Up => app.pan(0, -1),
+        Key::Down => app.pan(0, 1),
+        Key::BackTab => app.cycle_focus(),
+        Key::F(1) => app.toggle_controls(),
         _ => {}
     }
 }
@@ -18,17 +31,29 @@ pub fn handle_key_main(key: Key, app: &mut App) {
 fn handle_app_key(key: char, app: &mut App) {
     match key {
         's' => {
+            if !app.select_focused() {
+                return;
+            }
             if let Some(image_path) = app.current_image() {
                 app.push_action(Action::Skip(image_path));
             }
         }
         'z' => app.pop_action(),
+        'p' => app.toggle_pin(),
+        'n' => app.toggle_compare_next(),
+        'a' => app.toggle_playback(),
+        'b' => app.step_frame(-1),
+        'f' => app.step_frame(1),
+        'e' => app.toggle_histogram(),
         _ => {}
     }
 }
 
 fn handle_mapping_key(key: char, app: &mut App) {
     if let Some(mut path) = app.key_mapping.get_mut(&key).cloned() {
+        if !app.select_focused() {
+            return;
+        }
         if let Some(image_path) = app.current_image() {
             if let Some(Action::Rename(name)) = app.actions.last() {
                 path.push(name);
@@ -38,6 +63,27 @@ fn handle_mapping_key(key: char, app: &mut App) {
     }
 }
 
+pub fn handle_key_grid(key: Key, app: &mut App, columns: usize) {
+    match key {
+        Key::Left => app.grid_move(-1),
+        Key::Right => app.grid_move(1),
+        Key::Up => app.grid_move(-(columns as isize)),
+        Key::Down => app.grid_move(columns as isize),
+        Key::Char(' ') => app.grid_toggle_selection(),
+        Key::Ctrl('v') => app.grid_toggle_range(),
+        Key::Esc => app.grid_clear_selection(),
+        Key::Backspace => app.sort_grid_selection(Action::Delete),
+        Key::Ctrl('s') => app.sort_grid_selection(Action::Skip),
+        Key::Ctrl('z') => app.pop_action(),
+        Key::Char(key) => {
+            if let Some(path) = app.key_mapping.get(&key).cloned() {
+                app.sort_grid_selection(|image_path| Action::Move(image_path, path.clone()));
+            }
+        }
+        _ => {}
+    }
+}
+
 pub fn handle_key_script(key: Key, app: &mut App) {
     match key {
         Key::Up | Key::Char('k') => app.scroll_up(),
@@ -82,15 +128,11 @@ pub fn handle_key_input(key: Key, app: &mut App) {
         Key::Home | Key::Ctrl('a') => {
             app.input_idx = 0;
         }
-        Key::Left | Key::Ctrl('b') => {
-            if app.input_idx > 0 {
-                app.input_idx -= 1;
-            }
+        Key::Left | Key::Ctrl('b') if app.input_idx > 0 => {
+            app.input_idx -= 1;
         }
-        Key::Right | Key::Ctrl('f') => {
-            if app.input_idx < app.input.len() {
-                app.input_idx += 1;
-            }
+        Key::Right | Key::Ctrl('f') if app.input_idx < app.input.len() => {
+            app.input_idx += 1;
         }
         Key::Esc => {
             app.enable_input = false;
@@ -100,16 +142,12 @@ pub fn handle_key_input(key: Key, app: &mut App) {
             app.push_action(Action::Rename(input_str));
             app.enable_input = false;
         }
-        Key::Backspace | Key::Ctrl('h') => {
-            if !app.input.is_empty() && app.input_idx > 0 {
-                app.input.remove(app.input_idx - 1);
-                app.input_idx -= 1;
-            }
+        Key::Backspace | Key::Ctrl('h') if app.input_idx > 0 => {
+            app.input.remove(app.input_idx - 1);
+            app.input_idx -= 1;
         }
-        Key::Delete | Key::Ctrl('d') => {
-            if !app.input.is_empty() && app.input_idx < app.input.len() {
-                app.input.remove(app.input_idx);
-            }
+        Key::Delete | Key::Ctrl('d') if app.input_idx < app.input.len() => {
+            app.input.remove(app.input_idx);
         }
         Key::Char(c) => {
             app.input.insert(app.input_idx, c);
@@ -1,21 +1,43 @@
+mod animation;
 mod app;
+mod dimensions;
 mod event;
+mod format;
+mod heif;
+mod histogram;
 mod image_display;
 mod input;
+mod orientation;
+mod prefetch;
+mod raw;
 mod render;
+mod svg;
+mod thumbnails;
 
 use anyhow::{anyhow, Result};
 use expanduser::expanduser;
 use ratatui::{backend::TermionBackend, Terminal};
-use std::{io, path::PathBuf, time::Duration};
+use signal_hook::consts::SIGHUP;
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
 use structopt::StructOpt;
 use termion::{cursor::Goto, event::Key, raw::IntoRawMode, screen::IntoAlternateScreen};
 
 use crate::app::{App, TabId};
 use crate::event::{Event, EventsListener};
-use crate::image_display::ImageDisplay;
-use crate::input::{handle_key_input, handle_key_main, handle_key_script};
-use crate::render::{render_layout, render_main, render_script};
+use crate::format::Format;
+use crate::image_display::{
+    cache_failure, cache_image, new_image_display, take_awaited, Placement, Renderer,
+};
+use crate::input::{handle_key_grid, handle_key_input, handle_key_main, handle_key_script};
+use crate::prefetch::Prefetcher;
+use crate::render::{
+    grid_columns, render_exit_prompt, render_grid, render_layout, render_main, render_script,
+};
 
 fn parse_key_val(s: &str) -> Result<(char, PathBuf)> {
     let pos = s
@@ -64,11 +86,115 @@ pub struct Opt {
 
     #[structopt(short, long, help = "App tick rate (ms)", default_value = "1000")]
     tick_rate: u64,
+
+    #[structopt(
+        long,
+        help = "How to draw images: auto, blocks, iterm, kitty, sixel, w3m or none",
+        default_value = "auto"
+    )]
+    renderer: Renderer,
+
+    #[structopt(
+        long,
+        help = "How many of the next images to decode ahead of time",
+        default_value = "3"
+    )]
+    prefetch: usize,
+
+    #[structopt(
+        long,
+        help = "Don't read or write thumbnails in the cache shared with file managers"
+    )]
+    no_cache: bool,
+
+    #[structopt(
+        long,
+        help = "Only sort images of these types, e.g. jpeg,png,raw",
+        use_delimiter = true
+    )]
+    types: Vec<Format>,
+
+    #[structopt(
+        long,
+        help = "Only sort files with these extensions, e.g. jpg,jfif",
+        use_delimiter = true,
+        conflicts_with = "sniff"
+    )]
+    ext: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "Find images by their contents only, whatever their file extension"
+    )]
+    sniff: bool,
+
+    #[structopt(long, help = "List the image types which can be sorted, and exit")]
+    list_types: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(
+        name = "prune-cache",
+        about = "Remove the cached thumbnails of images moved or deleted by a script"
+    )]
+    PruneCache {
+        #[structopt(
+            help = "The script which sorted the images",
+            default_value = "sort.sh",
+            parse(from_os_str)
+        )]
+        script: PathBuf,
+    },
+}
+
+/// Print the image types, their extensions, whether `--sniff` finds them,
+/// and what it takes to show them.
+fn list_types() {
+    println!("{:<6}{:<32}{:<10}NOTES", "TYPE", "EXTENSIONS", "SNIFF");
+    for format in Format::ALL {
+        let notes = if format.needs_converter() {
+            match heif::installed_converter(format) {
+                Some(program) => format!("decoded with {}", program),
+                None => format!("needs one of {}", heif::converters(format).join(", ")),
+            }
+        } else if format == Format::Raw {
+            "shown by their embedded previews".to_string()
+        } else {
+            String::new()
+        };
+        let line = format!(
+            "{:<6}{:<32}{:<10}{}",
+            format.name(),
+            format.extensions().join(","),
+            format.found_by_content(),
+            notes
+        );
+        println!("{}", line.trim_end());
+    }
 }
 
 fn main() -> Result<()> {
     let opt = Opt::from_args();
-    let events_listener = EventsListener::new(Duration::from_millis(opt.tick_rate));
+    if let Some(Command::PruneCache { script }) = &opt.command {
+        let removed = thumbnails::prune(script)?;
+        println!("Removed {} thumbnails", removed);
+        return Ok(());
+    }
+    if opt.list_types {
+        list_types();
+        return Ok(());
+    }
+    if opt.no_cache {
+        thumbnails::disable();
+    }
+
+    let tick_rate = Duration::from_millis(opt.tick_rate);
+    let renderer = opt.renderer;
+    let prefetch = opt.prefetch;
     let mut app = App::new(opt)?;
 
     let stdout = io::stdout().into_raw_mode()?;
@@ -76,36 +202,151 @@ fn main() -> Result<()> {
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut image_display = ImageDisplay::new()?;
+    // Detecting the renderer may query the terminal, which must happen
+    // before the events listener starts reading stdin.
+    let mut image_display = new_image_display(renderer)?;
+    let events_listener = EventsListener::new(tick_rate)?;
+    let prefetcher = Prefetcher::new(events_listener.sender());
 
+    let mut last_view = None;
+    // images left blank until the prefetcher decodes them
+    let mut awaited = HashSet::new();
     loop {
-        terminal.draw(|f| {
-            let window = render_layout(f, &app);
-            if let Err(err) = match app.current_tab() {
-                TabId::Main => render_main(f, &app, &mut image_display, window),
-                TabId::Script => render_script(f, &app, window),
-            } {
-                eprintln!("ERROR: {:?}", err);
-                panic!("{}", err);
+        if let Some(image_path) = app.sync_playback() {
+            prefetcher.decode_animation(image_path);
+        }
+        if let Some(image_path) = app.sync_histogram() {
+            prefetcher.compute_histogram(image_path);
+        }
+        // ticks come faster while an animation plays, to show each frame on
+        // time, and while counting down in the exit prompt
+        let mut next_tick = tick_rate;
+        if let Some(next_frame_in) = app.next_frame_in() {
+            next_tick = next_tick.min(next_frame_in);
+        }
+        if app.exit_prompt.is_some() {
+            next_tick = next_tick.min(Duration::from_secs(1));
+        }
+        events_listener.set_tick_rate(next_tick);
+
+        // Nothing is drawn unless something on screen changed, ticks mostly
+        // find nothing to do.
+        let view = Some((app.view(), terminal.size()?));
+        if view != last_view {
+            last_view = view;
+
+            let mut rendered = Ok(());
+            let mut image_area = None;
+            terminal.draw(|f| {
+                let window = render_layout(f, &app);
+                if app.exit_prompt.is_some() {
+                    render_exit_prompt(f, &app, window);
+                    return;
+                }
+                rendered = match app.current_tab() {
+                    TabId::Main => render_main(f, &app, image_display.as_mut(), window)
+                        .map(|area| image_area = area),
+                    TabId::Script => render_script(f, &app, window),
+                    TabId::Grid => render_grid(f, &app, image_display.as_mut(), window),
+                };
+            })?;
+            // Failing to draw an image shouldn't take down the whole app, the
+            // error is shown in the status instead. The images which were
+            // rendered are drawn either way.
+            let flushed = image_display.flush();
+            if let Err(err) = rendered.and(flushed) {
+                app.report_error(err);
             }
-        })?;
 
-        if app.enable_input {
-            terminal.show_cursor()?;
-            let size = terminal.size()?;
-            print!("{}", Goto(app.input_idx as u16 + 2, size.height - 1))
-        } else {
-            terminal.hide_cursor()?;
+            // the images left blank come first, they're already on screen
+            let mut keys = take_awaited();
+            awaited.extend(keys.iter().cloned());
+            if let Some(block) = image_area {
+                keys.extend(
+                    app.neighbours(prefetch)
+                        .into_iter()
+                        .map(|image_path| Placement {
+                            image_path,
+                            block,
+                            zoom: app.zoom,
+                            animation_frame: 0,
+                            thumbnail: false,
+                        })
+                        .filter(|placement| image_display.decodes_image(placement))
+                        .map(|placement| placement.key(image_display.cell_pixels())),
+                );
+            }
+            if image_area.is_some() || !keys.is_empty() {
+                prefetcher.prefetch(keys);
+            }
+
+            if app.enable_input && app.exit_prompt.is_none() {
+                terminal.show_cursor()?;
+                let size = terminal.size()?;
+                print!("{}", Goto(app.input_idx as u16 + 2, size.height - 1));
+                // frames aren't drawn on every tick anymore, which used to
+                // flush this
+                io::stdout().flush()?;
+            } else {
+                terminal.hide_cursor()?;
+            }
         }
 
         match events_listener.next()? {
-            Event::Tick => continue,
+            Event::Tick => {
+                app.tick();
+                if app.exit_prompt_left() == Some(Duration::ZERO) {
+                    app.write()?;
+                    break;
+                }
+            }
+            Event::Prefetched(key, image) => {
+                if awaited.remove(&key) {
+                    last_view = None;
+                }
+                match image {
+                    Ok(image) => cache_image(key, image),
+                    Err(err) => cache_failure(key, err),
+                }
+            }
+            Event::Animation(image_path, animation) => app.start_playback(&image_path, animation),
+            Event::Histogram(image_path, histogram) => app.set_histogram(&image_path, histogram),
+            Event::Resize => {
+                // the previous layout could leave images, or parts of them,
+                // where there's only text now
+                terminal.clear()?;
+                if let Err(err) = image_display.reset() {
+                    app.report_error(err);
+                }
+                last_view = None;
+            }
+            Event::Terminate(signal) => {
+                if !app.has_unsaved_changes() {
+                    break;
+                }
+                // nobody is left to answer after a hangup, nor when asked to
+                // quit again
+                if signal == SIGHUP || app.exit_prompt.is_some() {
+                    app.write()?;
+                    break;
+                }
+                app.prompt_exit();
+            }
             Event::Input(key) => {
                 if key == Key::Ctrl('c') {
                     break;
                 }
 
-                if app.enable_input {
+                if app.exit_prompt.is_some() {
+                    match key {
+                        Key::Char('y') => {
+                            app.write()?;
+                            break;
+                        }
+                        Key::Char('n') => break,
+                        _ => {}
+                    }
+                } else if app.enable_input {
                     handle_key_input(key, &mut app);
                 } else {
                     // App controls
@@ -116,6 +357,10 @@ fn main() -> Result<()> {
                         _ => match app.current_tab() {
                             TabId::Main => handle_key_main(key, &mut app),
                             TabId::Script => handle_key_script(key, &mut app),
+                            TabId::Grid => {
+                                let columns = grid_columns(terminal.size()?);
+                                handle_key_grid(key, &mut app, columns)
+                            }
                         },
                     }
                 }
@@ -0,0 +1,93 @@
+use image::DynamicImage;
+use std::{fs::File, io::BufReader, path::Path};
+
+use crate::format::Format;
+use crate::raw;
+
+/// The EXIF Orientation tag: how the stored pixels must be transformed to
+/// show the image the right way up. Phones mostly store photos as the sensor
+/// saw them, and tag them with a rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    Transpose,
+    Rotate90,
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+    /// Read the orientation from the EXIF data in a JPEG, TIFF, PNG, WebP or
+    /// RAW file. Images without one are shown as they are stored. HEIF and
+    /// AVIF images are turned by their converter already.
+    pub fn read(image_path: &Path) -> Orientation {
+        let format = Format::sniff(image_path);
+        if format.is_some_and(Format::needs_converter) {
+            return Orientation::Normal;
+        }
+
+        let exif = File::open(image_path).ok().and_then(|file| {
+            exif::Reader::new()
+                .read_from_container(&mut BufReader::new(file))
+                .ok()
+        });
+        // CR3 and RAF aren't containers the EXIF reader knows
+        let exif = exif.or_else(|| match format {
+            Some(Format::Raw) => raw::exif(image_path),
+            _ => None,
+        });
+        let value = exif.as_ref().and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+                .value
+                .get_uint(0)
+        });
+
+        match value {
+            Some(2) => Orientation::FlipHorizontal,
+            Some(3) => Orientation::Rotate180,
+            Some(4) => Orientation::FlipVertical,
+            Some(5) => Orientation::Transpose,
+            Some(6) => Orientation::Rotate90,
+            Some(7) => Orientation::Transverse,
+            Some(8) => Orientation::Rotate270,
+            _ => Orientation::Normal,
+        }
+    }
+
+    /// Whether the width and height of the stored image are swapped.
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            Orientation::Transpose
+                | Orientation::Rotate90
+                | Orientation::Transverse
+                | Orientation::Rotate270
+        )
+    }
+
+    /// Width and height of the image once oriented.
+    pub fn dimensions(self, (width, height): (u32, u32)) -> (u32, u32) {
+        if self.swaps_dimensions() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Orientation::Normal => image,
+            Orientation::FlipHorizontal => image.fliph(),
+            Orientation::Rotate180 => image.rotate180(),
+            Orientation::FlipVertical => image.flipv(),
+            Orientation::Transpose => image.rotate90().fliph(),
+            Orientation::Rotate90 => image.rotate90(),
+            Orientation::Transverse => image.rotate270().fliph(),
+            Orientation::Rotate270 => image.rotate270(),
+        }
+    }
+}
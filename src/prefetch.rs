@@ -0,0 +1,97 @@
+use crossbeam_channel::{unbounded, Sender};
+use std::{path::PathBuf, sync::Arc, thread};
+
+use crate::animation::Animation;
+use crate::event::Event;
+use crate::histogram::histogram;
+use crate::image_display::{is_cached, ImageKey};
+
+enum Request {
+    Images(Vec<ImageKey>),
+    Animation(PathBuf),
+    Histogram(PathBuf),
+}
+
+/// Decodes the images likely to be shown next on a worker thread, so that
+/// moving on to them doesn't wait on the disk or the decoder. Decoded images
+/// are sent back to the main loop as `Event::Prefetched`, animations as
+/// `Event::Animation` and histograms as `Event::Histogram`.
+pub struct Prefetcher {
+    requests: Sender<Request>,
+}
+
+impl Prefetcher {
+    pub fn new(events: Sender<Event>) -> Self {
+        let (requests, rx) = unbounded::<Request>();
+
+        thread::spawn(move || {
+            while let Ok(request) = rx.recv() {
+                // only the latest requests matter, the others are for images
+                // the user already moved past
+                let (mut keys, mut animation, mut histogram_of) = (vec![], None, None);
+                for request in Some(request).into_iter().chain(rx.try_iter()) {
+                    match request {
+                        Request::Images(newer) => keys = newer,
+                        Request::Animation(image_path) => animation = Some(image_path),
+                        Request::Histogram(image_path) => histogram_of = Some(image_path),
+                    }
+                }
+
+                // the animation and histogram are shown right away, the
+                // images only later
+                if let Some(image_path) = animation {
+                    let animation = Animation::decode(&image_path).map(|a| a.map(Arc::new));
+                    if events
+                        .send(Event::Animation(image_path, animation))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+
+                if let Some(image_path) = histogram_of {
+                    let histogram = histogram(&image_path);
+                    if events
+                        .send(Event::Histogram(image_path, histogram))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+
+                for key in keys {
+                    if !rx.is_empty() {
+                        break;
+                    }
+                    if is_cached(&key) {
+                        continue;
+                    }
+                    // a broken image is reported when it's shown, not ahead
+                    // of time
+                    let image = key.decode();
+                    if events.send(Event::Prefetched(key, image)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Prefetcher { requests }
+    }
+
+    /// Decode `keys`, in order, replacing what was asked before.
+    pub fn prefetch(&self, keys: Vec<ImageKey>) {
+        // the worker only stops when the main loop is gone
+        let _ = self.requests.send(Request::Images(keys));
+    }
+
+    /// Decode the frames of `image_path`, if it's animated.
+    pub fn decode_animation(&self, image_path: PathBuf) {
+        let _ = self.requests.send(Request::Animation(image_path));
+    }
+
+    /// Compute the histogram of `image_path`.
+    pub fn compute_histogram(&self, image_path: PathBuf) {
+        let _ = self.requests.send(Request::Histogram(image_path));
+    }
+}
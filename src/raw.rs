@@ -0,0 +1,554 @@
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageFormat};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::dimensions::{boxes, find_box, jpeg_frame, read_bytes};
+
+// Walking the IFDs stops after this many, broken files may link them in a
+// loop or point anywhere
+const MAX_IFDS: usize = 64;
+// SubIFDs lists longer than this are cut short
+const MAX_VALUES: u32 = 16;
+
+// CR3 boxes holding the preview and the metadata, see
+// https://github.com/lclevy/canon_cr3
+const CR3_PREVIEW_UUID: [u8; 16] = [
+    0xea, 0xf4, 0x2b, 0x5e, 0x1c, 0x98, 0x4b, 0x88, 0xb9, 0xfb, 0xb7, 0xdc, 0x40, 0x6e, 0x4d, 0x16,
+];
+const CR3_METADATA_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// Whether the file is a camera RAW file this can show: TIFF based ones
+/// (CR2, NEF, ARW, DNG and ORF), RAF or CR3.
+pub fn is_raw(path: &Path) -> bool {
+    let mut header = [0u8; 12];
+    let read = File::open(path).and_then(|mut file| file.read_exact(&mut header));
+    read.is_ok() && Container::of(&header).is_some()
+}
+
+/// The dimensions of the preview a RAW file is shown with.
+pub fn preview_dimensions(path: &Path) -> Result<(u32, u32)> {
+    Ok(largest_preview(path)?.dimensions)
+}
+
+/// Decode the largest JPEG preview embedded in a RAW file. It's what the
+/// camera made of the picture, without decoding the sensor data.
+pub fn decode_preview(path: &Path) -> Result<DynamicImage> {
+    let preview = largest_preview(path)?;
+    let mut jpeg = vec![];
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(preview.offset))?;
+    reader.take(preview.length).read_to_end(&mut jpeg)?;
+    Ok(image::load_from_memory_with_format(
+        &jpeg,
+        ImageFormat::Jpeg,
+    )?)
+}
+
+/// The EXIF data of RAW files which aren't TIFF based, for their
+/// orientation: CR3 keeps it in a box of its own, RAF in its preview.
+pub fn exif(path: &Path) -> Option<exif::Exif> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).ok()?;
+
+    let tiff = match Container::of(&header)? {
+        Container::Cr3 => {
+            let (start, end) = cr3_metadata(&mut reader).ok()?;
+            let mut tiff = vec![];
+            reader.seek(SeekFrom::Start(start)).ok()?;
+            (&mut reader)
+                .take(end - start)
+                .read_to_end(&mut tiff)
+                .ok()?;
+            tiff
+        }
+        Container::Raf => {
+            let preview = largest_preview(path).ok()?;
+            let mut jpeg = vec![];
+            reader.seek(SeekFrom::Start(preview.offset)).ok()?;
+            reader.take(preview.length).read_to_end(&mut jpeg).ok()?;
+            return exif::Reader::new()
+                .read_from_container(&mut Cursor::new(jpeg))
+                .ok();
+        }
+        Container::Tiff { .. } => return None,
+    };
+    exif::Reader::new().read_raw(tiff).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Tiff { big_endian: bool },
+    Raf,
+    Cr3,
+}
+
+impl Container {
+    fn of(header: &[u8; 12]) -> Option<Container> {
+        match header {
+            // ORF replaces the TIFF magic number with "RO" or "RS"
+            [b'I', b'I', 42, 0, ..] | [b'I', b'I', b'R', b'O' | b'S', ..] => {
+                Some(Container::Tiff { big_endian: false })
+            }
+            [b'M', b'M', 0, 42, ..] | [b'M', b'M', b'O' | b'S', b'R', ..] => {
+                Some(Container::Tiff { big_endian: true })
+            }
+            [b'F', b'U', b'J', b'I', b'F', b'I', b'L', b'M', ..] => Some(Container::Raf),
+            [_, _, _, _, b'f', b't', b'y', b'p', b'c', b'r', b'x', b' '] => Some(Container::Cr3),
+            _ => None,
+        }
+    }
+}
+
+/// A JPEG embedded in a RAW file.
+struct Preview {
+    offset: u64,
+    length: u64,
+    dimensions: (u32, u32),
+}
+
+fn largest_preview(path: &Path) -> Result<Preview> {
+    let mut reader = BufReader::new(File::open(path)?);
+    largest_preview_in(&mut reader)?.ok_or_else(|| anyhow!("no preview in {}", path.display()))
+}
+
+/// The largest JPEG preview in a RAW file, `None` if it's not a format this
+/// knows, or there's none. Any offset in the file may be broken.
+fn largest_preview_in<R: Read + Seek>(reader: &mut R) -> Result<Option<Preview>> {
+    let header = read_bytes(reader)?;
+    let file_length = reader.seek(SeekFrom::End(0))?;
+
+    let candidates = match Container::of(&header) {
+        Some(Container::Tiff { big_endian }) => tiff_previews(reader, big_endian)?,
+        Some(Container::Raf) => raf_previews(reader)?,
+        Some(Container::Cr3) => cr3_previews(reader, file_length)?,
+        None => return Ok(None),
+    };
+
+    let mut largest: Option<Preview> = None;
+    for (offset, length) in candidates {
+        if length == 0 || offset.saturating_add(length) > file_length {
+            continue;
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        // baseline, extended and progressive JPEG only, the sensor data is
+        // often lossless JPEG, which can't be decoded
+        let dimensions = match jpeg_frame(reader) {
+            Ok((0xc0..=0xc2, dimensions)) => dimensions,
+            _ => continue,
+        };
+
+        let area = |(width, height): (u32, u32)| width as u64 * height as u64;
+        if largest
+            .as_ref()
+            .is_none_or(|largest| area(dimensions) > area(largest.dimensions))
+        {
+            largest = Some(Preview {
+                offset,
+                length,
+                dimensions,
+            });
+        }
+    }
+    Ok(largest)
+}
+
+/// An IFD entry, and where its value field is in the file.
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value_position: u64,
+}
+
+/// Reads the IFDs of a TIFF file, or of a maker note, whose offsets count
+/// from `base`.
+struct Tiff<'a, R> {
+    reader: &'a mut R,
+    big_endian: bool,
+    base: u64,
+}
+
+impl<R: Read + Seek> Tiff<'_, R> {
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = read_bytes(self.reader)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = read_bytes(self.reader)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// The entries of the IFD at `offset`, and the offset of the next one.
+    fn ifd(&mut self, offset: u32) -> Result<(Vec<Entry>, u32)> {
+        self.reader
+            .seek(SeekFrom::Start(self.base + offset as u64))?;
+        let mut entries = vec![];
+        for _ in 0..self.u16()? {
+            let tag = self.u16()?;
+            let field_type = self.u16()?;
+            let count = self.u32()?;
+            let value_position = self.reader.stream_position()?;
+            self.reader.seek(SeekFrom::Current(4))?;
+            entries.push(Entry {
+                tag,
+                field_type,
+                count,
+                value_position,
+            });
+        }
+        Ok((entries, self.u32()?))
+    }
+
+    /// The values of a SHORT, LONG or IFD entry.
+    fn values(&mut self, entry: &Entry) -> Result<Vec<u32>> {
+        let size = match entry.field_type {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return Ok(vec![]),
+        };
+        let count = entry.count.min(MAX_VALUES);
+
+        // values which don't fit in the value field are elsewhere, it holds
+        // their offset instead
+        if entry.count as u64 * size > 4 {
+            let offset = self.offset(entry)?;
+            self.reader
+                .seek(SeekFrom::Start(self.base + offset as u64))?;
+        } else {
+            self.reader.seek(SeekFrom::Start(entry.value_position))?;
+        }
+        (0..count)
+            .map(|_| match size {
+                2 => self.u16().map(u32::from),
+                _ => self.u32(),
+            })
+            .collect()
+    }
+
+    /// The offset in the value field of an entry.
+    fn offset(&mut self, entry: &Entry) -> Result<u32> {
+        self.reader.seek(SeekFrom::Start(entry.value_position))?;
+        self.u32()
+    }
+
+    fn value(&mut self, entries: &[Entry], tag: u16) -> Option<u32> {
+        let entry = entries.iter().find(|entry| entry.tag == tag)?;
+        self.values(entry).ok()?.first().copied()
+    }
+}
+
+/// Where the JPEGs of a TIFF based RAW file may be, in every IFD: in IFD0
+/// and the ones after it, their SubIFDs, and the EXIF IFD.
+fn tiff_previews<R: Read + Seek>(reader: &mut R, big_endian: bool) -> Result<Vec<(u64, u64)>> {
+    let mut tiff = Tiff {
+        reader,
+        big_endian,
+        base: 0,
+    };
+    tiff.reader.seek(SeekFrom::Start(4))?;
+    let mut pending = vec![tiff.u32()?];
+    let mut visited = HashSet::new();
+    let mut candidates = vec![];
+
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Ok((entries, next)) = tiff.ifd(offset) else {
+            continue;
+        };
+        pending.push(next);
+
+        for entry in &entries {
+            match entry.tag {
+                // SubIFDs and the EXIF IFD
+                330 | 34665 => pending.extend(tiff.values(entry).unwrap_or_default()),
+                // MakerNote
+                37500 => {
+                    if let Ok(offset) = tiff.offset(entry) {
+                        candidates.extend(olympus_previews(tiff.reader, offset as u64));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // JPEGInterchangeFormat and its length, e.g. in NEF and ARW
+        if let (Some(offset), Some(length)) = (tiff.value(&entries, 513), tiff.value(&entries, 514))
+        {
+            candidates.push((offset as u64, length as u64));
+        }
+        // a single strip of old style or new style JPEG, e.g. in CR2 and DNG
+        let compression = tiff.value(&entries, 259);
+        let strips = (tiff.value(&entries, 273), tiff.value(&entries, 279));
+        if let (Some(6 | 7), (Some(offset), Some(length))) = (compression, strips) {
+            candidates.push((offset as u64, length as u64));
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// The preview in an Olympus maker note, which is its own little TIFF:
+/// "OLYMPUS\0", the byte order, a version, then IFD0, with offsets counting
+/// from the start of the maker note.
+fn olympus_previews<R: Read + Seek>(reader: &mut R, maker_note: u64) -> Option<(u64, u64)> {
+    reader.seek(SeekFrom::Start(maker_note)).ok()?;
+    let header: [u8; 12] = read_bytes(reader).ok()?;
+    let big_endian = match header {
+        [b'O', b'L', b'Y', b'M', b'P', b'U', b'S', 0, b'I', b'I', ..] => false,
+        [b'O', b'L', b'Y', b'M', b'P', b'U', b'S', 0, b'M', b'M', ..] => true,
+        _ => return None,
+    };
+
+    let mut tiff = Tiff {
+        reader,
+        big_endian,
+        base: maker_note,
+    };
+    let (entries, _) = tiff.ifd(12).ok()?;
+    // CameraSettings, then PreviewImageStart and PreviewImageLength
+    let camera_settings = tiff.value(&entries, 0x2020)?;
+    let (entries, _) = tiff.ifd(camera_settings).ok()?;
+    let offset = tiff.value(&entries, 0x0101)?;
+    let length = tiff.value(&entries, 0x0102)?;
+    Some((maker_note + offset as u64, length as u64))
+}
+
+/// RAF starts with a header holding the offset and length of its JPEG.
+fn raf_previews<R: Read + Seek>(reader: &mut R) -> Result<Vec<(u64, u64)>> {
+    reader.seek(SeekFrom::Start(84))?;
+    let offset = u32::from_be_bytes(read_bytes(reader)?);
+    let length = u32::from_be_bytes(read_bytes(reader)?);
+    Ok(vec![(offset as u64, length as u64)])
+}
+
+/// CR3 has a medium sized preview in a PRVW box, and the first sample of
+/// its first track is a JPEG as large as the picture.
+fn cr3_previews<R: Read + Seek>(reader: &mut R, file_length: u64) -> Result<Vec<(u64, u64)>> {
+    let mut candidates = vec![];
+    let top_level = boxes(reader, 0, file_length)?;
+
+    if let Some(&(_, start, end)) = top_level.iter().find(|(box_type, start, _)| {
+        box_type == b"uuid" && is_uuid(reader, *start, &CR3_PREVIEW_UUID)
+    }) {
+        // the UUID and 8 unknown bytes come before PRVW
+        if let Ok((prvw_start, _)) = find_box(reader, start + 24, end, b"PRVW") {
+            // unknown (6 bytes), width, height, unknown (2 bytes), then the
+            // length of the JPEG following it
+            reader.seek(SeekFrom::Start(prvw_start + 12))?;
+            let length = u32::from_be_bytes(read_bytes(reader)?);
+            candidates.push((prvw_start + 16, length as u64));
+        }
+    }
+
+    let (moov_start, moov_end) = find_box(reader, 0, file_length, b"moov")?;
+    for (box_type, start, end) in boxes(reader, moov_start, moov_end)? {
+        if &box_type != b"trak" {
+            continue;
+        }
+        if let Ok(sample) = first_sample(reader, start, end) {
+            candidates.push(sample);
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// The offset and length of the first sample of a track, from its sample
+/// table.
+fn first_sample<R: Read + Seek>(reader: &mut R, start: u64, end: u64) -> Result<(u64, u64)> {
+    let (mdia_start, mdia_end) = find_box(reader, start, end, b"mdia")?;
+    let (minf_start, minf_end) = find_box(reader, mdia_start, mdia_end, b"minf")?;
+    let (stbl_start, stbl_end) = find_box(reader, minf_start, minf_end, b"stbl")?;
+
+    // stsz and stco are full boxes, with a version and flags first
+    let (stsz_start, _) = find_box(reader, stbl_start, stbl_end, b"stsz")?;
+    reader.seek(SeekFrom::Start(stsz_start + 4))?;
+    let mut length = u32::from_be_bytes(read_bytes(reader)?);
+    if length == 0 {
+        // samples of different sizes, listed after their count
+        reader.seek(SeekFrom::Current(4))?;
+        length = u32::from_be_bytes(read_bytes(reader)?);
+    }
+
+    let offset = match find_box(reader, stbl_start, stbl_end, b"co64") {
+        Ok((co64_start, _)) => {
+            reader.seek(SeekFrom::Start(co64_start + 8))?;
+            u64::from_be_bytes(read_bytes(reader)?)
+        }
+        Err(_) => {
+            let (stco_start, _) = find_box(reader, stbl_start, stbl_end, b"stco")?;
+            reader.seek(SeekFrom::Start(stco_start + 8))?;
+            u32::from_be_bytes(read_bytes(reader)?) as u64
+        }
+    };
+    Ok((offset, length as u64))
+}
+
+/// The TIFF holding the EXIF IFD0 of a CR3 file, in the CMT1 box.
+fn cr3_metadata<R: Read + Seek>(reader: &mut R) -> Result<(u64, u64)> {
+    let file_length = reader.seek(SeekFrom::End(0))?;
+    let (moov_start, moov_end) = find_box(reader, 0, file_length, b"moov")?;
+    for (box_type, start, end) in boxes(reader, moov_start, moov_end)? {
+        if &box_type == b"uuid" && is_uuid(reader, start, &CR3_METADATA_UUID) {
+            return find_box(reader, start + 16, end, b"CMT1");
+        }
+    }
+    Err(anyhow!("no metadata in CR3"))
+}
+
+fn is_uuid<R: Read + Seek>(reader: &mut R, start: u64, uuid: &[u8; 16]) -> bool {
+    reader.seek(SeekFrom::Start(start)).is_ok()
+        && read_bytes::<_, 16>(reader).is_ok_and(|found| &found == uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dimensions::iso_box;
+
+    /// The start of a JPEG, up to its frame header, which is all that's
+    /// read of previews.
+    fn jpeg(marker: u8, width: u16, height: u16) -> Vec<u8> {
+        let (width, height) = (width.to_be_bytes(), height.to_be_bytes());
+        vec![
+            0xff, 0xd8, 0xff, marker, 0, 11, 8, height[0], height[1], width[0], width[1], 0, 0,
+        ]
+    }
+
+    /// A little endian IFD of LONG entries, or SHORT ones for Compression.
+    fn ifd(entries: &[(u16, u32)], next: u32) -> Vec<u8> {
+        let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+        for &(tag, value) in entries {
+            let field_type: u16 = if tag == 259 { 3 } else { 4 };
+            ifd.extend_from_slice(&tag.to_le_bytes());
+            ifd.extend_from_slice(&field_type.to_le_bytes());
+            ifd.extend_from_slice(&1u32.to_le_bytes());
+            ifd.extend_from_slice(&value.to_le_bytes());
+        }
+        ifd.extend_from_slice(&next.to_le_bytes());
+        ifd
+    }
+
+    fn largest(file: Vec<u8>) -> Option<(u64, (u32, u32))> {
+        largest_preview_in(&mut Cursor::new(file))
+            .unwrap()
+            .map(|preview| (preview.offset, preview.dimensions))
+    }
+
+    #[test]
+    fn follows_ifd_chains_once() {
+        let (small, large, lossless) = (
+            jpeg(0xc0, 160, 120),
+            jpeg(0xc2, 1600, 1200),
+            jpeg(0xc3, 4000, 3000),
+        );
+        // IFD0 at 8 has the small preview, and SubIFDs at 50 with the large
+        // one and the sensor data, whose next IFD is IFD0 again
+        let data = 8 + 42 + 66;
+        let large_offset = data + small.len() as u32;
+        let lossless_offset = large_offset + large.len() as u32;
+        let file = [
+            &b"II*\0\x08\0\0\0"[..],
+            &ifd(&[(330, 50), (513, data), (514, small.len() as u32)], 50),
+            &ifd(
+                &[
+                    (259, 7),
+                    (273, lossless_offset),
+                    (279, lossless.len() as u32),
+                    (513, large_offset),
+                    (514, large.len() as u32),
+                ],
+                8,
+            ),
+            &small,
+            &large,
+            &lossless,
+        ]
+        .concat();
+
+        assert_eq!(largest(file), Some((large_offset as u64, (1600, 1200))));
+    }
+
+    #[test]
+    fn skips_offsets_out_of_the_file() {
+        let file = [
+            &b"II*\0\x08\0\0\0"[..],
+            &ifd(
+                &[
+                    (330, 0xffff_fff0),
+                    (513, 0xffff_ffff),
+                    (514, 0xffff_ffff),
+                    (34665, 1_000_000),
+                ],
+                0xffff_0000,
+            ),
+            &jpeg(0xc0, 160, 120),
+        ]
+        .concat();
+
+        assert_eq!(largest(file), None);
+    }
+
+    #[test]
+    fn reads_raf_header() {
+        let preview = jpeg(0xc1, 1920, 1280);
+        let mut file = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        file.resize(84, 0);
+        file.extend_from_slice(&100u32.to_be_bytes());
+        file.extend_from_slice(&(preview.len() as u32).to_be_bytes());
+        file.resize(100, 0);
+        file.extend_from_slice(&preview);
+
+        assert_eq!(largest(file), Some((100, (1920, 1280))));
+    }
+
+    #[test]
+    fn reads_cr3_prvw() {
+        let preview = jpeg(0xc0, 1620, 1080);
+        let prvw = iso_box(
+            b"PRVW",
+            &[
+                &[0; 6][..],
+                &1620u16.to_be_bytes(),
+                &1080u16.to_be_bytes(),
+                &[0; 2],
+                &(preview.len() as u32).to_be_bytes(),
+                &preview,
+            ]
+            .concat(),
+        );
+        let ftyp = iso_box(b"ftyp", b"crx \0\0\0\x01crx isom");
+        let uuid = iso_box(b"uuid", &[&CR3_PREVIEW_UUID[..], &[0; 8], &prvw].concat());
+        // a track without a sample table, as if it were broken
+        let moov = iso_box(b"moov", &iso_box(b"trak", &iso_box(b"mdia", &[])));
+        // sizes, types, UUID and the unknown bytes, then the PRVW header
+        let offset = ftyp.len() + 8 + 16 + 8 + 8 + 16;
+        let file = [ftyp, uuid, moov].concat();
+
+        assert_eq!(largest(file), Some((offset as u64, (1620, 1080))));
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        assert_eq!(largest(jpeg(0xc0, 16, 16)), None);
+    }
+}
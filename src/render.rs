@@ -1,17 +1,25 @@
 use anyhow::Result;
 use ratatui::{
     backend::Backend,
+    buffer::Buffer,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     terminal::Frame,
-    text::{Line, Text},
-    widgets::{Block, Borders, Paragraph, Row, Table, Tabs},
+    text::{Line, Span, Text},
+    widgets::{block::Title, Block, Borders, Paragraph, Row, Sparkline, Table, Tabs, Widget, Wrap},
+};
+use std::{
+    env,
+    path::{self, Path, PathBuf},
 };
-use std::{env, path, time::Duration};
 use tico::tico;
 
-use crate::app::{Action, App};
-use crate::image_display::ImageDisplay;
+use crate::app::{Action, App, Pane, Zoom};
+use crate::dimensions::read_dimensions;
+use crate::format::Format;
+use crate::histogram::buckets;
+use crate::image_display::{ImageDisplay, Placement, Viewport};
+use crate::orientation::Orientation;
 
 pub fn render_layout<B>(f: &mut Frame<B>, app: &App) -> Rect
 where
@@ -23,7 +31,11 @@ where
         .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
         .split(window);
 
-    let titles = ["Main", "Script"].iter().cloned().map(Line::from).collect();
+    let titles = ["Main", "Script", "Grid"]
+        .iter()
+        .cloned()
+        .map(Line::from)
+        .collect();
 
     let tabs = Tabs::new(titles)
         .block(Block::default().title("image-sorter").borders(Borders::ALL))
@@ -34,72 +46,434 @@ where
     layout[1]
 }
 
+/// Returns the area the current image is drawn in, to decode the next ones
+/// for the same size ahead of time.
 pub fn render_main<B>(
     f: &mut Frame<B>,
     app: &App,
-    image_display: &mut ImageDisplay,
+    image_display: &mut dyn ImageDisplay,
     window: Rect,
-) -> Result<()>
+) -> Result<Option<Rect>>
 where
     B: Backend,
 {
-    let image_title = match app.current_image() {
-        None => "No more images left to sort".to_string(),
-        Some(image_path) => {
-            let image_path = image_path.display().to_string();
-            if let Some(Action::Rename(name)) = app.actions.last() {
-                format!("{} - Renamed to {}", image_path, name)
-            } else {
-                image_path
-            }
-        }
-    };
-    let image_block = Block::default().borders(Borders::ALL).title(image_title);
-
     let window_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Min(10), Constraint::Length(30)].as_ref())
         .split(window);
 
-    let main_layout_constraints = if app.enable_input {
-        vec![Constraint::Min(10), Constraint::Length(3)]
-    } else {
-        vec![Constraint::Min(10)]
-    };
+    let mut main_layout_constraints =
+        vec![Constraint::Min(10), Constraint::Length(FILMSTRIP_HEIGHT)];
+    if app.enable_input {
+        main_layout_constraints.push(Constraint::Length(3));
+    }
 
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(main_layout_constraints)
         .split(window_layout[0]);
 
+    let mut sidebar_constraints = vec![Constraint::Length(3), Constraint::Min(5)];
+    if app.show_histogram {
+        sidebar_constraints.push(Constraint::Length(HISTOGRAM_HEIGHT));
+    }
+    // borders and header
+    sidebar_constraints.push(Constraint::Length(controls(app).len() as u16 + 3));
     let sidebar_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Length(3),
-                Constraint::Min(5),
-                Constraint::Length(11),
-            ]
-            .as_ref(),
-        )
+        .constraints(sidebar_constraints)
         .split(window_layout[1]);
 
-    render_status(f, app, sidebar_layout[0]);
+    let rotated = image_display.auto_rotates()
+        && app
+            .current_image()
+            .is_some_and(|image_path| Orientation::read(&image_path) != Orientation::Normal);
+
+    render_status(f, app, rotated, sidebar_layout[0]);
     render_key_mapping(f, app, sidebar_layout[1]);
-    render_controls(f, sidebar_layout[2]);
+    if app.show_histogram {
+        render_histogram(f, app, sidebar_layout[2]);
+    }
+    render_controls(f, app, sidebar_layout[sidebar_layout.len() - 1]);
 
-    if let Some(image_path) = app.current_image() {
-        let terminal_size = f.size();
-        let image_container = image_block.inner(main_layout[0]);
-        image_display.render_image(image_path, image_container, terminal_size)?;
+    let panes = app.panes();
+    if panes.is_empty() {
+        let image_block = Block::default()
+            .borders(Borders::ALL)
+            .title("No more images left to sort");
+        f.render_widget(image_block, main_layout[0]);
+    }
+
+    let panes_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, panes.len() as u32); panes.len()])
+        .split(main_layout[0]);
+    let mut image_area = None;
+    for ((pane, image_path), window) in panes.iter().zip(panes_layout.iter()) {
+        let focused = panes.len() > 1 && *pane == app.focus;
+        let area = render_pane(f, app, image_display, *pane, image_path, focused, *window)?;
+        if *pane == Pane::Current {
+            image_area = Some(area);
+        }
     }
 
-    f.render_widget(image_block, main_layout[0]);
     if app.enable_input {
-        render_rename_input(f, app, main_layout[1]);
+        render_rename_input(f, app, main_layout[2]);
     }
 
-    Ok(())
+    render_filmstrip(f, app, image_display, main_layout[1])?;
+    Ok(image_area)
+}
+
+fn render_pane<B>(
+    f: &mut Frame<B>,
+    app: &App,
+    image_display: &mut dyn ImageDisplay,
+    pane: Pane,
+    image_path: &Path,
+    focused: bool,
+    window: Rect,
+) -> Result<Rect>
+where
+    B: Backend,
+{
+    let image_path_str = image_path.display().to_string();
+    let image_title = match pane {
+        Pane::Pinned => format!("Pinned: {}", image_path_str),
+        Pane::Next => format!("Next: {}", image_path_str),
+        Pane::Current => {
+            if let Some(Action::Rename(name)) = app.actions.last() {
+                format!("{} - Renamed to {}", image_path_str, name)
+            } else {
+                image_path_str
+            }
+        }
+    };
+    let mut image_block = Block::default().borders(Borders::ALL).title(image_title);
+    if focused {
+        image_block = image_block.border_style(Style::default().fg(Color::Yellow));
+    }
+
+    let image_container = image_block.inner(window);
+    if let Some(indicator) = zoom_indicator(app, image_display, image_path, image_container) {
+        image_block = image_block.title(Title::from(indicator).alignment(Alignment::Right));
+    }
+    if let Some((frame, frame_count, paused)) =
+        app.playback_state().filter(|_| pane == Pane::Current)
+    {
+        let state = if paused { "paused" } else { "playing" };
+        let indicator = format!("Frame {}/{}, {}", frame + 1, frame_count, state);
+        image_block = image_block.title(Title::from(indicator).alignment(Alignment::Right));
+    }
+    f.render_widget(image_block, window);
+
+    let mut result = Ok(());
+    let image = ImageWidget {
+        image_display,
+        image_path: image_path.to_path_buf(),
+        zoom: app.zoom,
+        animation_frame: app.frame(image_path),
+        thumbnail: false,
+        result: &mut result,
+    };
+    f.render_widget(image, image_container);
+    result.map(|_| image_container)
+}
+
+/// Shown instead of the tabs when the app was asked to quit by a signal,
+/// with changes which aren't saved yet.
+pub fn render_exit_prompt<B>(f: &mut Frame<B>, app: &App, window: Rect)
+where
+    B: Backend,
+{
+    let left = app.exit_prompt_left().unwrap_or_default().as_secs() + 1;
+    let prompt_block = Block::default()
+        .borders(Borders::ALL)
+        .title("Asked to quit");
+    let prompt = Paragraph::new(format!(
+        "Save the script to {} before quitting? (y/n)\n\nSaving it anyway in {}s",
+        app.output, left
+    ))
+    .alignment(Alignment::Center)
+    .block(prompt_block);
+    f.render_widget(prompt, window);
+}
+
+// Size of a thumbnail in the grid, borders included
+const THUMBNAIL_WIDTH: u16 = 24;
+const THUMBNAIL_HEIGHT: u16 = 12;
+
+/// How many thumbnails fit in a row of the grid, in a terminal of `size`.
+pub fn grid_columns(size: Rect) -> usize {
+    (size.width.saturating_sub(2) / THUMBNAIL_WIDTH).max(1) as usize
+}
+
+pub fn render_grid<B>(
+    f: &mut Frame<B>,
+    app: &App,
+    image_display: &mut dyn ImageDisplay,
+    window: Rect,
+) -> Result<()>
+where
+    B: Backend,
+{
+    let grid_block = Block::default().borders(Borders::ALL).title(
+        "Space: select, Ctrl-V: select range, Esc: clear selection, sorting keys apply to the selection",
+    );
+    let grid_area = grid_block.inner(window);
+    f.render_widget(grid_block, window);
+
+    let queue = &app.images[app.current..];
+    if queue.is_empty() {
+        let paragraph = Paragraph::new("No more images left to sort").alignment(Alignment::Center);
+        f.render_widget(paragraph, grid_area);
+        return Ok(());
+    }
+
+    let columns = grid_columns(f.size());
+    let rows = (grid_area.height / THUMBNAIL_HEIGHT).max(1) as usize;
+    let cursor = app.grid_cursor - app.current;
+    // scroll a page at a time, so thumbnails don't move under the cursor
+    let first = cursor / (rows * columns) * (rows * columns);
+
+    let mut result = Ok(());
+    for (i, image_path) in queue.iter().enumerate().skip(first).take(rows * columns) {
+        let (row, column) = ((i - first) / columns, (i - first) % columns);
+        let window = Rect::new(
+            grid_area.x + column as u16 * THUMBNAIL_WIDTH,
+            grid_area.y + row as u16 * THUMBNAIL_HEIGHT,
+            THUMBNAIL_WIDTH,
+            THUMBNAIL_HEIGHT,
+        )
+        .intersection(grid_area);
+
+        let name = image_path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let mut thumbnail_block = Block::default().borders(Borders::ALL);
+        if app.is_grid_selected(app.current + i) {
+            thumbnail_block = thumbnail_block
+                .title(format!("✓ {}", name))
+                .border_style(Style::default().fg(Color::Green));
+        } else {
+            thumbnail_block = thumbnail_block.title(name);
+        }
+        if i == cursor {
+            thumbnail_block = thumbnail_block.border_style(Style::default().fg(Color::Yellow));
+        }
+
+        // one broken image shouldn't hide the others
+        let rendered = render_thumbnail(f, image_display, image_path, thumbnail_block, window);
+        if result.is_ok() {
+            result = rendered;
+        }
+    }
+
+    result
+}
+
+// Height of the filmstrip below the Main tab's image, borders included
+const FILMSTRIP_HEIGHT: u16 = 8;
+const FILMSTRIP_THUMBNAIL_WIDTH: u16 = 16;
+
+/// A strip of the last few sorted images, each with the key it was sorted
+/// with, then the current image and the next few in the queue.
+fn render_filmstrip<B>(
+    f: &mut Frame<B>,
+    app: &App,
+    image_display: &mut dyn ImageDisplay,
+    window: Rect,
+) -> Result<()>
+where
+    B: Backend,
+{
+    let slots = (window.width / FILMSTRIP_THUMBNAIL_WIDTH) as usize;
+    let upcoming = &app.images[app.current.min(app.images.len())..];
+    let decided: Vec<(&PathBuf, Span)> = app
+        .actions
+        .iter()
+        .rev()
+        .filter_map(|action| decision_badge(app, action))
+        .take(slots.saturating_sub(1) / 2)
+        .collect();
+
+    let mut thumbnails: Vec<(&PathBuf, Block)> = decided
+        .into_iter()
+        .rev()
+        .map(|(image_path, badge)| (image_path, Block::default().title(badge)))
+        .collect();
+    for (i, image_path) in upcoming.iter().enumerate() {
+        let name = image_path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let mut thumbnail_block = Block::default().title(name);
+        if i == 0 {
+            thumbnail_block = thumbnail_block.border_style(Style::default().fg(Color::Yellow));
+        }
+        thumbnails.push((image_path, thumbnail_block));
+    }
+
+    let mut result = Ok(());
+    for (i, (image_path, thumbnail_block)) in thumbnails.into_iter().take(slots).enumerate() {
+        let window = Rect::new(
+            window.x + i as u16 * FILMSTRIP_THUMBNAIL_WIDTH,
+            window.y,
+            FILMSTRIP_THUMBNAIL_WIDTH,
+            window.height,
+        );
+        let thumbnail_block = thumbnail_block.borders(Borders::ALL);
+        let rendered = render_thumbnail(f, image_display, image_path, thumbnail_block, window);
+        if result.is_ok() {
+            result = rendered;
+        }
+    }
+
+    result
+}
+
+/// The image an action sorted, with a badge telling where it went: the key
+/// of its destination, or whether it was skipped or deleted.
+fn decision_badge<'a>(app: &App, action: &'a Action) -> Option<(&'a PathBuf, Span<'static>)> {
+    let badge_style = Style::default().fg(Color::Black);
+    match action {
+        Action::Move(image_path, destination) => {
+            // a move after a rename names the file too
+            let key = app
+                .key_mapping
+                .iter()
+                .find(|(_, path)| *path == destination)
+                .or_else(|| {
+                    app.key_mapping
+                        .iter()
+                        .find(|(_, path)| destination.parent() == Some(path.as_path()))
+                })
+                .map_or("?".to_string(), |(key, _)| key.to_string());
+            let badge = Span::styled(format!(" {} ", key), badge_style.bg(Color::Cyan));
+            Some((image_path, badge))
+        }
+        Action::Delete(image_path) => {
+            let badge = Span::styled(" Deleted ", badge_style.bg(Color::Red));
+            Some((image_path, badge))
+        }
+        Action::Skip(image_path) => {
+            let badge = Span::styled(" Skipped ", badge_style.bg(Color::Gray));
+            Some((image_path, badge))
+        }
+        Action::Rename(_) | Action::MkDir(_) => None,
+    }
+}
+
+fn render_thumbnail<B>(
+    f: &mut Frame<B>,
+    image_display: &mut dyn ImageDisplay,
+    image_path: &Path,
+    thumbnail_block: Block,
+    window: Rect,
+) -> Result<()>
+where
+    B: Backend,
+{
+    let thumbnail_area = thumbnail_block.inner(window);
+    f.render_widget(thumbnail_block, window);
+
+    let mut result = Ok(());
+    let image = ImageWidget {
+        image_display,
+        image_path: image_path.to_path_buf(),
+        zoom: Zoom::Fit,
+        animation_frame: 0,
+        thumbnail: true,
+        result: &mut result,
+    };
+    f.render_widget(image, thumbnail_area);
+    result
+}
+
+/// Hands the frame's buffer over to the image display.
+struct ImageWidget<'a> {
+    image_display: &'a mut dyn ImageDisplay,
+    image_path: PathBuf,
+    zoom: Zoom,
+    animation_frame: usize,
+    thumbnail: bool,
+    result: &'a mut Result<()>,
+}
+
+impl Widget for ImageWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let supported_formats = self.image_display.supported_formats();
+        if let Some(format) =
+            Format::sniff(&self.image_path).filter(|format| !supported_formats.contains(format))
+        {
+            let message = format!("{} images can't be shown with this renderer", format.name());
+            Paragraph::new(message)
+                .wrap(Wrap { trim: true })
+                .render(area, buf);
+            return;
+        }
+
+        let placement = Placement {
+            image_path: self.image_path,
+            block: area,
+            zoom: self.zoom,
+            animation_frame: self.animation_frame,
+            thumbnail: self.thumbnail,
+        };
+        *self.result = self.image_display.render_image(placement, buf);
+    }
+}
+
+const MINIMAP_WIDTH: u64 = 8;
+
+/// The zoom level, with a minimap of the part of the image which is visible,
+/// i.e. `200% x ░▓▓▓░░░░ y ░░░▓▓▓░░`.
+fn zoom_indicator(
+    app: &App,
+    image_display: &dyn ImageDisplay,
+    image_path: &Path,
+    block: Rect,
+) -> Option<String> {
+    let percent = match app.zoom {
+        Zoom::Fit => return None,
+        Zoom::Scale { percent, .. } => percent,
+    };
+
+    let orientation = if image_display.auto_rotates() {
+        Orientation::read(image_path)
+    } else {
+        Orientation::Normal
+    };
+    let (width, height) = orientation.dimensions(read_dimensions(image_path).ok()?);
+    let (cell_width, cell_height) = image_display.cell_pixels();
+    let viewport = Viewport::new(
+        width,
+        height,
+        block.width as u32 * cell_width,
+        block.height as u32 * cell_height,
+        app.zoom,
+    );
+
+    let minimap = |start: u32, length: u32, total: u32| -> String {
+        let (start, end, total) = (start as u64, (start + length) as u64, total as u64);
+        (0..MINIMAP_WIDTH)
+            .map(|i| {
+                let (cell_start, cell_end) =
+                    (total * i / MINIMAP_WIDTH, total * (i + 1) / MINIMAP_WIDTH);
+                if cell_end > start && cell_start < end {
+                    '▓'
+                } else {
+                    '░'
+                }
+            })
+            .collect()
+    };
+    let (x, y, crop_width, crop_height) = viewport.crop;
+    Some(format!(
+        "{}% x {} y {}",
+        percent,
+        minimap(x, crop_width, width),
+        minimap(y, crop_height, height)
+    ))
 }
 
 fn render_rename_input<B>(f: &mut Frame<B>, app: &App, window: Rect)
@@ -117,20 +491,23 @@ where
     f.render_widget(paragraph, window);
 }
 
-const STATUS_DURATION: Duration = Duration::from_secs(2);
-
-fn render_status<B>(f: &mut Frame<B>, app: &App, window: Rect)
+fn render_status<B>(f: &mut Frame<B>, app: &App, rotated: bool, window: Rect)
 where
     B: Backend,
 {
     let status_block = Block::default().borders(Borders::ALL).title("Status");
     let mut status = format!("Sorted: {}/{}", app.current, app.images.len());
-    if let Some(last_save) = app.last_save {
-        if last_save.elapsed() < STATUS_DURATION {
-            status = "Script saved!".to_string();
-        }
+    if rotated {
+        status.push_str(" (auto-rotated)");
     }
-    let paragraph = Paragraph::new(Text::from(status))
+    let mut status = Text::from(status);
+    if app.saved_recently() {
+        status = Text::from("Script saved!");
+    }
+    if let Some(error) = app.recent_error() {
+        status = Text::styled(error, Style::default().fg(Color::Red));
+    }
+    let paragraph = Paragraph::new(status)
         .alignment(Alignment::Center)
         .block(status_block);
     f.render_widget(paragraph, window);
@@ -176,24 +553,129 @@ where
     f.render_widget(key_mapping, window);
 }
 
-fn render_controls<B>(f: &mut Frame<B>, window: Rect)
+// Borders, the luminance sparkline, one for each of red, green and blue, and
+// the exposure warnings
+const HISTOGRAM_HEIGHT: u16 = 2 + 4 + 3 * 2 + 2;
+
+/// Histograms of the current image, with how much of it is over or under
+/// exposed.
+fn render_histogram<B>(f: &mut Frame<B>, app: &App, window: Rect)
+where
+    B: Backend,
+{
+    let histogram_block = Block::default().borders(Borders::ALL).title("Histogram");
+    let area = histogram_block.inner(window);
+    f.render_widget(histogram_block, window);
+
+    // it's left blank until the prefetcher is done computing it
+    let histogram = match app.current_histogram() {
+        Some(Ok(histogram)) => histogram,
+        Some(Err(err)) => {
+            let error = Paragraph::new(err.to_string()).style(Style::default().fg(Color::Red));
+            f.render_widget(error, area);
+            return;
+        }
+        None => return,
+    };
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(4),
+                Constraint::Length(2),
+                Constraint::Length(2),
+                Constraint::Length(2),
+                Constraint::Length(2),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    let channels = [
+        (&histogram.luma, Color::White),
+        (&histogram.red, Color::Red),
+        (&histogram.green, Color::Green),
+        (&histogram.blue, Color::Blue),
+    ];
+    for ((levels, color), window) in channels.iter().zip(layout.iter()) {
+        let data = buckets(levels, window.width as usize);
+        let sparkline = Sparkline::default()
+            .data(&data)
+            .style(Style::default().fg(*color));
+        f.render_widget(sparkline, *window);
+    }
+
+    let warning = |percent: f64| {
+        if percent >= EXPOSURE_WARNING_PERCENT {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        }
+    };
+    let (clipped, crushed) = (histogram.clipped_percent(), histogram.crushed_percent());
+    let exposure = Text::from(vec![
+        Line::styled(
+            format!("Clipped highlights: {:.1}%", clipped),
+            warning(clipped),
+        ),
+        Line::styled(
+            format!("Crushed shadows: {:.1}%", crushed),
+            warning(crushed),
+        ),
+    ]);
+    f.render_widget(Paragraph::new(exposure), layout[4]);
+}
+
+// Above this share of clipped or crushed pixels, it's shown in red
+const EXPOSURE_WARNING_PERCENT: f64 = 1.0;
+
+/// The keys shown in the Controls table. The ones for zooming, comparing and
+/// such are only listed on demand, they'd push the key mapping off the
+/// screen.
+fn controls(app: &App) -> Vec<[&'static str; 2]> {
+    let mut controls = vec![
+        ["Ctrl-C", "Exit"],
+        ["Tab", "Switch tabs"],
+        ["", ""],
+        ["Ctrl-R", "Rename image"],
+        ["Ctrl-S", "Skip image"],
+        ["Backspace", "Delete image"],
+        ["Ctrl-Z", "Undo action"],
+        ["Ctrl-W", "Save script"],
+    ];
+    if !app.show_all_controls {
+        controls.push(["F1", "More controls"]);
+        return controls;
+    }
+
+    controls.extend([
+        ["", ""],
+        ["PgUp/PgDn", "Zoom in/out"],
+        ["Home/End", "Zoom to fit/100%"],
+        ["Arrows", "Pan zoomed image"],
+        ["", ""],
+        ["Ctrl-P", "Pin image"],
+        ["Ctrl-N", "Compare with next"],
+        ["Shift-Tab", "Focus next pane"],
+        ["", ""],
+        ["Ctrl-A", "Play/pause animation"],
+        ["Ctrl-B/F", "Previous/next frame"],
+        ["Ctrl-E", "Toggle histogram"],
+        ["F1", "Fewer controls"],
+    ]);
+    controls
+}
+
+fn render_controls<B>(f: &mut Frame<B>, app: &App, window: Rect)
 where
     B: Backend,
 {
     let controls_block = Block::default().borders(Borders::ALL).title("Controls");
-    let controls = Table::new(vec![
-        Row::new(["Ctrl-C", "Exit"]),
-        Row::new(["Tab", "Switch tabs"]),
-        Row::new(["", ""]),
-        Row::new(["Ctrl-R", "Rename image"]),
-        Row::new(["Ctrl-S", "Skip image"]),
-        Row::new(["Backspace", "Delete image"]),
-        Row::new(["Ctrl-Z", "Undo action"]),
-        Row::new(["Ctrl-W", "Save script"]),
-    ])
-    .widths([Constraint::Length(10), Constraint::Length(20)].as_ref())
-    .header(Row::new(["Key", "Action"]).style(Style::default().fg(Color::Red)))
-    .block(controls_block);
+    let controls = Table::new(controls(app).into_iter().map(Row::new))
+        .widths([Constraint::Length(10), Constraint::Length(20)].as_ref())
+        .header(Row::new(["Key", "Action"]).style(Style::default().fg(Color::Red)))
+        .block(controls_block);
 
     f.render_widget(controls, window);
 }
@@ -237,7 +719,6 @@ where
         }
     }
 
-    let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
     let script_block = Block::default().borders(Borders::ALL);
     let paragraph = Paragraph::new(lines)
         .block(script_block)
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, RgbaImage};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{fontdb::Database, Options, Tree},
+};
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use crate::app::Zoom;
+use crate::image_display::Viewport;
+
+// SVG files start with an XML declaration, comments or a doctype more often
+// than not, the svg element is looked for in this many bytes
+const SNIFF_LENGTH: u64 = 4096;
+
+/// The fonts installed, for text in SVG. Loading them takes a while, so it's
+/// only done once.
+static FONTS: OnceLock<Arc<Database>> = OnceLock::new();
+
+/// Whether the file is an SVG, or a gzip compressed one.
+pub fn is_svg(path: &Path) -> bool {
+    let mut start = vec![];
+    let read = File::open(path).and_then(|file| file.take(SNIFF_LENGTH).read_to_end(&mut start));
+    read.is_ok()
+        && (start.starts_with(&[0x1f, 0x8b]) || start.windows(4).any(|window| window == b"<svg"))
+}
+
+/// The size of an SVG, which it's drawn at when zoomed to 100%.
+pub fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    let size = parse(path)?.size();
+    Ok((
+        size.width().ceil().max(1.0) as u32,
+        size.height().ceil().max(1.0) as u32,
+    ))
+}
+
+/// Draw an SVG at its own size.
+pub fn decode(path: &Path) -> Result<DynamicImage> {
+    let (width, height) = dimensions(path)?;
+    rasterize(path, Zoom::Fit, width, height)
+}
+
+/// Draw an SVG right at the size it's shown: filling `max_width` x
+/// `max_height` pixels when it fits, however small it is, or only the part
+/// which is visible when zoomed in.
+pub fn rasterize(path: &Path, zoom: Zoom, max_width: u32, max_height: u32) -> Result<DynamicImage> {
+    let tree = parse(path)?;
+    let size = tree.size();
+    let (width, height) = (size.width(), size.height());
+
+    let (transform, (pixmap_width, pixmap_height)) = match zoom {
+        Zoom::Fit => {
+            let scale = (max_width as f32 / width).min(max_height as f32 / height);
+            let scaled = (
+                (width * scale).round().max(1.0) as u32,
+                (height * scale).round().max(1.0) as u32,
+            );
+            (Transform::from_scale(scale, scale), scaled)
+        }
+        Zoom::Scale { .. } => {
+            let (width, height) = (width.ceil() as u32, height.ceil() as u32);
+            let viewport = Viewport::new(width, height, max_width, max_height, zoom);
+            let (x, y, crop_width, crop_height) = viewport.crop;
+            let (scaled_width, scaled_height) = viewport.size;
+            let scale_x = scaled_width as f32 / crop_width as f32;
+            let scale_y = scaled_height as f32 / crop_height as f32;
+            let transform = Transform::from_row(
+                scale_x,
+                0.0,
+                0.0,
+                scale_y,
+                -(x as f32) * scale_x,
+                -(y as f32) * scale_y,
+            );
+            (transform, viewport.size)
+        }
+    };
+
+    let mut pixmap = Pixmap::new(pixmap_width, pixmap_height).ok_or_else(|| {
+        anyhow!(
+            "can't draw {} at {}x{}",
+            path.display(),
+            pixmap_width,
+            pixmap_height
+        )
+    })?;
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia premultiplies colors by their alpha, the image crate doesn't
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let image = RgbaImage::from_raw(pixmap_width, pixmap_height, pixels)
+        .ok_or_else(|| anyhow!("can't draw {}", path.display()))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+fn parse(path: &Path) -> Result<Tree> {
+    let fonts = FONTS.get_or_init(|| {
+        let mut fonts = Database::new();
+        fonts.load_system_fonts();
+        Arc::new(fonts)
+    });
+    let options = Options {
+        // images and such referenced by the SVG are relative to it
+        resources_dir: path.parent().map(Path::to_path_buf),
+        fontdb: fonts.clone(),
+        ..Options::default()
+    };
+    Ok(Tree::from_data(&fs::read(path)?, &options)?)
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+use std::{
+    env,
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use crate::app::Zoom;
+use crate::format::Format;
+use crate::image_display::decode_image;
+use crate::orientation::Orientation;
+use crate::svg;
+
+/// The sizes of thumbnails in the cache, by the largest of their width and
+/// height, see
+/// https://specifications.freedesktop.org/thumbnail-spec/latest/
+const FLAVORS: [(&str, u32); 4] = [
+    ("normal", 128),
+    ("large", 256),
+    ("x-large", 512),
+    ("xx-large", 1024),
+];
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stop reading and writing thumbnails in the cache, for `--no-cache`.
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// A thumbnail of `image_path` of at least `size` pixels, from the cache
+/// shared with file managers. It's generated and saved there when it's
+/// missing or older than the image. Returns `None` when the cache is disabled
+/// or has no thumbnails that large.
+pub fn load(image_path: &Path, size: u32) -> Result<Option<DynamicImage>> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let Some(&(flavor, flavor_size)) = FLAVORS.iter().find(|(_, flavor_size)| *flavor_size >= size)
+    else {
+        return Ok(None);
+    };
+
+    let uri = file_uri(&fs::canonicalize(image_path)?);
+    let mtime = fs::metadata(image_path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    let thumbnail_path = cache_dir()?.join(flavor).join(thumbnail_name(&uri));
+
+    if is_valid(&thumbnail_path, &uri, mtime) {
+        if let Ok(thumbnail) = image::open(&thumbnail_path) {
+            return Ok(Some(thumbnail));
+        }
+    }
+
+    let (thumbnail, (width, height)) = if Format::sniff(image_path) == Some(Format::Svg) {
+        // drawn at the size of the flavor, however small the SVG is
+        let thumbnail = svg::rasterize(image_path, Zoom::Fit, flavor_size, flavor_size)?;
+        (thumbnail, svg::dimensions(image_path)?)
+    } else {
+        let image = decode_image(image_path)?;
+        let (width, height) = (image.width(), image.height());
+        // images smaller than the flavor are stored as they are
+        let thumbnail = if width > flavor_size || height > flavor_size {
+            image.thumbnail(flavor_size, flavor_size)
+        } else {
+            image
+        };
+        (thumbnail, (width, height))
+    };
+    let thumbnail = Orientation::read(image_path).apply(thumbnail);
+
+    // the image is shown anyway when the cache can't be written to
+    let _ = save(&thumbnail, &thumbnail_path, &uri, mtime, (width, height));
+    Ok(Some(thumbnail))
+}
+
+/// Remove the thumbnails of the images which `script` moved or deleted, they
+/// would never be used again. Returns how many were removed.
+pub fn prune(script: &Path) -> Result<usize> {
+    let cache_dir = cache_dir()?;
+    let script =
+        File::open(script).map_err(|err| anyhow!("can't read {}: {}", script.display(), err))?;
+
+    let mut removed = 0;
+    for line in BufReader::new(script).lines() {
+        let line = line?;
+        let Some(image_path) = sorted_image(&line) else {
+            continue;
+        };
+        // the script wasn't run yet, or the image is back
+        if image_path.exists() {
+            continue;
+        }
+
+        let uri = file_uri(&absolute_path(&image_path)?);
+        for (flavor, _) in FLAVORS {
+            let thumbnail_path = cache_dir.join(flavor).join(thumbnail_name(&uri));
+            if fs::remove_file(thumbnail_path).is_ok() {
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+/// The image moved or deleted by a line of a script written by the app, e.g.
+/// `mv "image.jpg" "folder"` or `rm "image.jpg"`.
+fn sorted_image(line: &str) -> Option<PathBuf> {
+    let quoted = line
+        .strip_prefix("mv \"")
+        .or_else(|| line.strip_prefix("rm \""))?;
+    let (image_path, _) = quoted.split_once('"')?;
+    Some(PathBuf::from(image_path))
+}
+
+/// Make `path` absolute without resolving it, as it doesn't exist anymore.
+/// The folder it was in usually still does.
+fn absolute_path(path: &Path) -> Result<PathBuf> {
+    let path = env::current_dir()?.join(path);
+    let canonical_parent = path
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok());
+    match (canonical_parent, path.file_name()) {
+        (Some(parent), Some(file_name)) => Ok(parent.join(file_name)),
+        _ => Ok(path),
+    }
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .filter(|cache_home| !cache_home.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .ok_or_else(|| anyhow!("neither XDG_CACHE_HOME nor HOME is set"))?;
+    Ok(cache_home.join("thumbnails"))
+}
+
+/// The URI of an absolute path, escaped like GLib does, so that thumbnails
+/// are found under the same name as by file managers.
+fn file_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"!$&'()*+,-./:=@_~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+fn thumbnail_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri))
+}
+
+/// Whether the thumbnail exists, and was made from the image as it is now.
+fn is_valid(thumbnail_path: &Path, uri: &str, mtime: u64) -> bool {
+    let Ok(file) = File::open(thumbnail_path) else {
+        return false;
+    };
+    let Ok(reader) = png::Decoder::new(BufReader::new(file)).read_info() else {
+        return false;
+    };
+
+    let text = &reader.info().uncompressed_latin1_text;
+    let field = |keyword: &str| {
+        text.iter()
+            .find(|chunk| chunk.keyword == keyword)
+            .map(|chunk| chunk.text.as_str())
+    };
+    field("Thumb::URI") == Some(uri) && field("Thumb::MTime") == Some(&mtime.to_string())
+}
+
+fn save(
+    thumbnail: &DynamicImage,
+    thumbnail_path: &Path,
+    uri: &str,
+    mtime: u64,
+    (width, height): (u32, u32),
+) -> Result<()> {
+    let dir = thumbnail_path
+        .parent()
+        .ok_or_else(|| anyhow!("no folder for {}", thumbnail_path.display()))?;
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+
+    // written next to it first, so nobody reads a partial thumbnail
+    let temporary_path = thumbnail_path.with_extension(format!("{}.tmp", process::id()));
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary_path)?;
+
+    let thumbnail = thumbnail.to_rgba8();
+    let mut encoder =
+        png::Encoder::new(BufWriter::new(file), thumbnail.width(), thumbnail.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk("Thumb::URI".to_string(), uri.to_string())?;
+    encoder.add_text_chunk("Thumb::MTime".to_string(), mtime.to_string())?;
+    encoder.add_text_chunk("Thumb::Image::Width".to_string(), width.to_string())?;
+    encoder.add_text_chunk("Thumb::Image::Height".to_string(), height.to_string())?;
+    encoder.add_text_chunk("Software".to_string(), env!("CARGO_PKG_NAME").to_string())?;
+
+    let written = encoder.write_header().and_then(|mut writer| {
+        writer.write_image_data(thumbnail.as_raw())?;
+        writer.finish()
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(err.into());
+    }
+    fs::rename(&temporary_path, thumbnail_path)?;
+    Ok(())
+}